@@ -1,41 +1,266 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::sysvar::slot_hashes;
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::{transfer_fee::TransferFeeAmount, BaseStateWithExtensions, StateWithExtensions},
+};
+use anchor_spl::token_2022_extensions::transfer_fee::{harvest_withheld_tokens_to_mint, HarvestWithheldTokensToMint};
+use anchor_spl::token_interface::{self, CloseAccount, Mint, TokenAccount, TokenInterface, TransferChecked};
+use solana_sha256_hasher::hashv;
 
 declare_id!("6fHsjMVqDo6rYk39uQ8GtTYVHrjuNNfq5PaMDft9ea3F");
 
+pub const BPS_DENOMINATOR: u64 = 10_000;
+pub const MAX_FEE_BPS: u16 = 1_000;
+pub const MAX_MILESTONES: usize = 8;
+pub const MAX_RING_PARTICIPANTS: usize = 5;
+pub const MAX_POOL_MEMBERS: usize = 32;
+pub const MAX_JURORS: usize = 5;
+/// Evidence entries each party may add to a single dispute
+pub const MAX_EVIDENCE_PER_PARTY: usize = 4;
+/// Counter-proposals allowed on a single offer or barter
+pub const MAX_NEGOTIATION_ROUNDS: u8 = 6;
+
+// String limits, matching the space reserved in each account's SIZE. They are
+// byte lengths as stored by Borsh, not character counts: a 64-byte title holds
+// 64 ASCII characters but only 16 four-byte emoji.
+pub const MAX_TITLE_LEN: usize = 64;
+pub const MAX_DESCRIPTION_LEN: usize = 256;
+pub const MAX_CATEGORY_LEN: usize = 32;
+pub const MAX_MESSAGE_LEN: usize = 256;
+pub const MAX_DELIVERY_HASH_LEN: usize = 64;
+pub const MAX_DELIVERY_CONTENT_LEN: usize = 512;
+pub const MAX_REASON_LEN: usize = 256;
+pub const MAX_BARTER_TERMS_LEN: usize = 256;
+pub const MAX_RING_TERMS_LEN: usize = 128;
+pub const MAX_EVIDENCE_URI_LEN: usize = 200;
+/// How long a client has to confirm or dispute a delivery before anyone can release escrow
+pub const REVIEW_WINDOW_SECS: i64 = 3 * 24 * 60 * 60;
+/// Default time both parties get to submit evidence after a dispute is raised
+pub const EVIDENCE_WINDOW_SECS: i64 = 2 * 24 * 60 * 60;
+
 #[program]
 pub mod clawswap {
     use super::*;
 
-    pub fn initialize(ctx: Context<Initialize>, _global_id: u64) -> Result<()> {
+    pub fn initialize(ctx: Context<Initialize>, global_id: u64) -> Result<()> {
         let global = &mut ctx.accounts.global;
+        global.global_id = global_id;
         global.authority = ctx.accounts.authority.key();
         global.need_counter = 0;
         global.offer_counter = 0;
         global.deal_counter = 0;
         global.barter_counter = 0;
+        global.ring_counter = 0;
+        global.category_counter = 0;
+        global.fee_bps = 0;
+        global.fee_treasury = ctx.accounts.authority.key();
+        global.pending_authority = None;
+        global.marketplace_paused = false;
+        global.barter_paused = false;
+        global.evidence_window_secs = EVIDENCE_WINDOW_SECS;
         global.bump = ctx.bumps.global;
         Ok(())
     }
 
+    pub fn set_paused(ctx: Context<SetPaused>, marketplace_paused: bool, barter_paused: bool) -> Result<()> {
+        let global = &mut ctx.accounts.global;
+        require!(ctx.accounts.authority.key() == global.authority, ErrorCode::NotAuthority);
+
+        global.marketplace_paused = marketplace_paused;
+        global.barter_paused = barter_paused;
+
+        emit!(PauseUpdated {
+            marketplace_paused,
+            barter_paused,
+        });
+
+        Ok(())
+    }
+
+    /// Zero disables the window: evidence is accepted until resolution, which isn't held back.
+    pub fn set_evidence_window(ctx: Context<SetEvidenceWindow>, evidence_window_secs: i64) -> Result<()> {
+        let global = &mut ctx.accounts.global;
+        require!(ctx.accounts.authority.key() == global.authority, ErrorCode::NotAuthority);
+        require!(evidence_window_secs >= 0, ErrorCode::InvalidEvidenceWindow);
+
+        global.evidence_window_secs = evidence_window_secs;
+
+        emit!(EvidenceWindowUpdated { evidence_window_secs });
+
+        Ok(())
+    }
+
+    pub fn propose_authority(ctx: Context<ProposeAuthority>, new_authority: Pubkey) -> Result<()> {
+        let global = &mut ctx.accounts.global;
+        require!(ctx.accounts.authority.key() == global.authority, ErrorCode::NotAuthority);
+
+        global.pending_authority = Some(new_authority);
+
+        emit!(AuthorityProposed {
+            authority: global.authority,
+            pending_authority: new_authority,
+        });
+
+        Ok(())
+    }
+
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let global = &mut ctx.accounts.global;
+        let new_authority = ctx.accounts.pending_authority.key();
+
+        require!(global.pending_authority.is_some(), ErrorCode::NoPendingAuthority);
+        require!(global.pending_authority == Some(new_authority), ErrorCode::NotPendingAuthority);
+
+        let previous_authority = global.authority;
+        global.authority = new_authority;
+        global.pending_authority = None;
+
+        emit!(AuthorityTransferred {
+            previous_authority,
+            new_authority,
+        });
+
+        Ok(())
+    }
+
+    pub fn renounce_authority(ctx: Context<RenounceAuthority>) -> Result<()> {
+        let global = &mut ctx.accounts.global;
+        require!(ctx.accounts.authority.key() == global.authority, ErrorCode::NotAuthority);
+
+        let previous_authority = global.authority;
+        global.authority = Pubkey::default();
+        global.pending_authority = None;
+
+        emit!(AuthorityRenounced { previous_authority });
+
+        Ok(())
+    }
+
+    /// SOL fees are credited to the treasury directly, and a credit that leaves a new
+    /// account below rent exemption fails the whole payout, so the treasury must
+    /// already be rent-exempt.
+    pub fn set_fee_config(ctx: Context<SetFeeConfig>, fee_bps: u16, fee_treasury: Pubkey) -> Result<()> {
+        let global = &mut ctx.accounts.global;
+        let treasury = &ctx.accounts.treasury;
+
+        require!(ctx.accounts.authority.key() == global.authority, ErrorCode::NotAuthority);
+        require!(fee_bps <= MAX_FEE_BPS, ErrorCode::FeeTooHigh);
+        require!(
+            Rent::get()?.is_exempt(treasury.lamports(), treasury.data_len()),
+            ErrorCode::TreasuryNotRentExempt
+        );
+
+        global.fee_bps = fee_bps;
+        global.fee_treasury = fee_treasury;
+
+        emit!(FeeConfigUpdated { fee_bps, fee_treasury });
+
+        Ok(())
+    }
+
+    // ── Categories ──
+
+    /// Names are unique per marketplace: each one claims a `CategoryName` marker, and a
+    /// marker already claimed by another category rejects the name.
+    pub fn create_category(
+        ctx: Context<CreateCategory>,
+        name: String,
+        fee_bps: Option<u16>,
+        min_budget_lamports: u64,
+    ) -> Result<()> {
+        let global = &mut ctx.accounts.global;
+        let category = &mut ctx.accounts.category;
+
+        require!(ctx.accounts.authority.key() == global.authority, ErrorCode::NotAuthority);
+        require!(name.len() <= MAX_CATEGORY_LEN, ErrorCode::CategoryTooLong);
+        require!(is_valid_category_name(&name), ErrorCode::InvalidCategoryName);
+        require!(fee_bps.is_none_or(|bps| bps <= MAX_FEE_BPS), ErrorCode::FeeTooHigh);
+
+        let category_name = &mut ctx.accounts.category_name;
+        require!(category_name.global == Pubkey::default(), ErrorCode::CategoryNameTaken);
+        category_name.global = global.key();
+        category_name.category_id = global.category_counter;
+        category_name.bump = ctx.bumps.category_name;
+
+        category.id = global.category_counter;
+        category.global = global.key();
+        category.name = name;
+        category.active = true;
+        category.fee_bps = fee_bps;
+        category.min_budget_lamports = min_budget_lamports;
+        category.bump = ctx.bumps.category;
+
+        global.category_counter += 1;
+
+        emit!(CategoryCreated {
+            id: category.id,
+            name: category.name.clone(),
+            fee_bps,
+            min_budget_lamports,
+        });
+
+        Ok(())
+    }
+
+    pub fn update_category(
+        ctx: Context<UpdateCategory>,
+        active: bool,
+        fee_bps: Option<u16>,
+        min_budget_lamports: u64,
+    ) -> Result<()> {
+        let category = &mut ctx.accounts.category;
+
+        require!(ctx.accounts.authority.key() == ctx.accounts.global.authority, ErrorCode::NotAuthority);
+        require!(fee_bps.is_none_or(|bps| bps <= MAX_FEE_BPS), ErrorCode::FeeTooHigh);
+
+        category.active = active;
+        category.fee_bps = fee_bps;
+        category.min_budget_lamports = min_budget_lamports;
+
+        emit!(CategoryUpdated {
+            id: category.id,
+            active,
+            fee_bps,
+            min_budget_lamports,
+        });
+
+        Ok(())
+    }
+
     pub fn create_need(
         ctx: Context<CreateNeed>,
         title: String,
         description: String,
-        category: String,
         budget_lamports: u64,
-        deadline: Option<i64>
+        deadline: Option<i64>,
+        budget_is_cap: bool,
     ) -> Result<()> {
         let global = &mut ctx.accounts.global;
         let need = &mut ctx.accounts.need;
-        
+        let category = &ctx.accounts.category;
+        let mint = ctx.accounts.mint.as_ref().map(|mint| mint.key());
+
+        require!(!global.marketplace_paused, ErrorCode::Paused);
+        require!(title.len() <= MAX_TITLE_LEN, ErrorCode::TitleTooLong);
+        require!(description.len() <= MAX_DESCRIPTION_LEN, ErrorCode::DescriptionTooLong);
+        require!(category.active, ErrorCode::CategoryInactive);
+        require!(category.allows_budget(mint, budget_lamports), ErrorCode::BelowCategoryMinimum);
+        let now = Clock::get()?.unix_timestamp;
+        require!(deadline.is_none_or(|deadline| deadline > now), ErrorCode::InvalidDeadline);
+
         need.id = global.need_counter;
+        need.global = global.key();
         need.creator = ctx.accounts.creator.key();
         need.title = title;
         need.description = description;
-        need.category = category;
+        need.category_id = category.id;
         need.budget_lamports = budget_lamports;
+        need.budget_is_cap = budget_is_cap;
+        need.mint = mint;
         need.status = NeedStatus::Open;
-        need.created_at = Clock::get()?.unix_timestamp;
+        need.created_at = now;
+        need.revision = 0;
         need.deadline = deadline;
         need.bump = ctx.bumps.need;
 
@@ -45,7 +270,9 @@ pub mod clawswap {
             id: need.id,
             creator: need.creator,
             title: need.title.clone(),
+            category_id: need.category_id,
             budget_lamports: need.budget_lamports,
+            mint: need.mint,
         });
 
         Ok(())
@@ -56,20 +283,34 @@ pub mod clawswap {
         need_id: u64,
         price_lamports: u64,
         message: String,
+        delivery_secs: Option<i64>,
+        milestones: Vec<u64>,
     ) -> Result<()> {
         let global = &mut ctx.accounts.global;
         let offer = &mut ctx.accounts.offer;
         let need = &ctx.accounts.need;
 
+        require!(!global.marketplace_paused, ErrorCode::Paused);
         require!(need.status == NeedStatus::Open, ErrorCode::NeedNotOpen);
+        require!(need.allows_price(price_lamports), ErrorCode::OverBudget);
+        require!(message.len() <= MAX_MESSAGE_LEN, ErrorCode::MessageTooLong);
+        require!(delivery_secs.is_none_or(|secs| secs > 0), ErrorCode::InvalidDeliveryWindow);
+        validate_milestones(&milestones, price_lamports)?;
         
         offer.id = global.offer_counter;
+        offer.global = global.key();
         offer.need_id = need_id;
         offer.provider = ctx.accounts.provider.key();
         offer.price_lamports = price_lamports;
+        offer.mint = need.mint;
         offer.message = message;
+        offer.delivery_secs = delivery_secs;
+        offer.milestones = milestones;
         offer.status = OfferStatus::Pending;
         offer.created_at = Clock::get()?.unix_timestamp;
+        offer.revision = 0;
+        offer.negotiation_round = 0;
+        offer.proposed_by = offer.provider;
         offer.bump = ctx.bumps.offer;
 
         global.offer_counter += 1;
@@ -79,30 +320,79 @@ pub mod clawswap {
             need_id: offer.need_id,
             provider: offer.provider,
             price_lamports: offer.price_lamports,
+            delivery_secs: offer.delivery_secs,
+            milestones: offer.milestones.clone(),
         });
 
         Ok(())
     }
 
-    pub fn accept_offer(ctx: Context<AcceptOffer>) -> Result<()> {
+    /// Competing offers on the same need can be passed as writable remaining
+    /// accounts; they are marked rejected alongside the acceptance.
+    /// `expected_max_price` guards the client against the offer's price moving
+    /// between when they reviewed it and when this lands.
+    pub fn accept_offer<'info>(
+        ctx: Context<'_, '_, 'info, 'info, AcceptOffer<'info>>,
+        expected_max_price: u64,
+    ) -> Result<()> {
+        require!(!ctx.accounts.global.marketplace_paused, ErrorCode::Paused);
         require!(ctx.accounts.need.status == NeedStatus::Open, ErrorCode::NeedNotOpen);
         require!(ctx.accounts.offer.status == OfferStatus::Pending, ErrorCode::OfferNotPending);
         require!(ctx.accounts.need.creator == ctx.accounts.client.key(), ErrorCode::NotNeedCreator);
+        require!(ctx.accounts.offer.need_id == ctx.accounts.need.id, ErrorCode::OfferNeedMismatch);
+        require!(ctx.accounts.offer.proposed_by == ctx.accounts.offer.provider, ErrorCode::CounterPending);
 
-        let price = ctx.accounts.offer.price_lamports;
+        require!(ctx.accounts.offer.mint == ctx.accounts.need.mint, ErrorCode::MintMismatch);
 
-        // Transfer SOL to escrow (deal PDA) — before mutable borrows
-        let transfer_ix = anchor_lang::system_program::Transfer {
-            from: ctx.accounts.client.to_account_info(),
-            to: ctx.accounts.deal.to_account_info(),
-        };
-        anchor_lang::system_program::transfer(
-            CpiContext::new(
-                ctx.accounts.system_program.to_account_info(),
-                transfer_ix,
-            ),
-            price,
-        )?;
+        let price = ctx.accounts.offer.price_lamports;
+        require!(price <= expected_max_price, ErrorCode::PriceAboveExpected);
+        require!(ctx.accounts.need.allows_price(price), ErrorCode::OverBudget);
+        let mut escrowed = price;
+
+        match ctx.accounts.need.mint {
+            None => {
+                // Transfer SOL to escrow (deal PDA) — before mutable borrows
+                let transfer_ix = anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.client.to_account_info(),
+                    to: ctx.accounts.deal.to_account_info(),
+                };
+                anchor_lang::system_program::transfer(
+                    CpiContext::new(
+                        ctx.accounts.system_program.to_account_info(),
+                        transfer_ix,
+                    ),
+                    price,
+                )?;
+            }
+            Some(need_mint) => {
+                let mint = ctx.accounts.mint.as_ref().ok_or(ErrorCode::TokenAccountsMissing)?;
+                let client_token_account = ctx
+                    .accounts
+                    .client_token_account
+                    .as_ref()
+                    .ok_or(ErrorCode::TokenAccountsMissing)?;
+                let token_program = ctx.accounts.token_program.as_ref().ok_or(ErrorCode::TokenAccountsMissing)?;
+                let vault = ctx.accounts.vault.as_mut().ok_or(ErrorCode::TokenAccountsMissing)?;
+                require_keys_eq!(mint.key(), need_mint, ErrorCode::MintMismatch);
+
+                // Transfer tokens to escrow (deal-owned vault)
+                let transfer_ix = TransferChecked {
+                    from: client_token_account.to_account_info(),
+                    mint: mint.to_account_info(),
+                    to: vault.to_account_info(),
+                    authority: ctx.accounts.client.to_account_info(),
+                };
+                token_interface::transfer_checked(
+                    CpiContext::new(token_program.to_account_info(), transfer_ix),
+                    price,
+                    mint.decimals,
+                )?;
+
+                // Transfer-fee mints withhold part of the transfer, so escrow what actually arrived
+                vault.reload()?;
+                escrowed = vault.amount;
+            }
+        }
 
         // Now take mutable borrows
         let global = &mut ctx.accounts.global;
@@ -115,13 +405,30 @@ pub mod clawswap {
         offer.status = OfferStatus::Accepted;
 
         deal.id = global.deal_counter;
+        deal.global = global.key();
         deal.need_id = need.id;
         deal.offer_id = offer.id;
         deal.client = ctx.accounts.client.key();
         deal.provider = offer.provider;
-        deal.amount_lamports = price;
+        deal.amount_lamports = escrowed;
+        deal.released_lamports = 0;
+        // A deal without milestones is paid out as a single one
+        deal.milestones = if offer.milestones.is_empty() {
+            vec![escrowed]
+        } else {
+            offer.milestones.clone()
+        };
+        deal.current_milestone = 0;
+        deal.mint = need.mint;
+        deal.fee_bps = ctx.accounts.category.fee_bps.unwrap_or(global.fee_bps);
         deal.status = DealStatus::InProgress;
         deal.created_at = Clock::get()?.unix_timestamp;
+        // The provider's own delivery commitment wins over the need's deadline
+        deal.delivery_deadline = match offer.delivery_secs {
+            Some(secs) => Some(deal.created_at + secs),
+            None => need.deadline,
+        };
+        deal.review_deadline = None;
         deal.delivery_hash = None;
         deal.delivery_content = None;
         deal.dispute_reason = None;
@@ -129,6 +436,8 @@ pub mod clawswap {
 
         global.deal_counter += 1;
 
+        reject_competing_offers(ctx.remaining_accounts, global.key(), need.id, offer.id)?;
+
         emit!(DealCreated {
             id: deal.id,
             need_id: deal.need_id,
@@ -136,6 +445,10 @@ pub mod clawswap {
             client: deal.client,
             provider: deal.provider,
             amount_lamports: deal.amount_lamports,
+            mint: deal.mint,
+            delivery_deadline: deal.delivery_deadline,
+            milestones: deal.milestones.clone(),
+            negotiation_round: offer.negotiation_round,
         });
 
         Ok(())
@@ -148,19 +461,29 @@ pub mod clawswap {
     ) -> Result<()> {
         let deal = &mut ctx.accounts.deal;
         
+        let now = Clock::get()?.unix_timestamp;
+
         require!(deal.status == DealStatus::InProgress, ErrorCode::DealNotInProgress);
         require!(deal.provider == ctx.accounts.provider.key(), ErrorCode::NotProvider);
-        require!(delivery_content.len() <= 512, ErrorCode::DeliveryContentTooLong);
+        require!(delivery_hash.len() <= MAX_DELIVERY_HASH_LEN, ErrorCode::DeliveryHashTooLong);
+        require!(delivery_content.len() <= MAX_DELIVERY_CONTENT_LEN, ErrorCode::DeliveryContentTooLong);
+        require!(
+            deal.delivery_deadline.is_none_or(|deadline| now <= deadline),
+            ErrorCode::DeliveryDeadlinePassed
+        );
 
         deal.delivery_hash = Some(delivery_hash.clone());
         deal.delivery_content = Some(delivery_content.clone());
         deal.status = DealStatus::DeliverySubmitted;
+        deal.review_deadline = Some(now + REVIEW_WINDOW_SECS);
 
         emit!(DeliverySubmitted {
             deal_id: deal.id,
+            milestone: deal.current_milestone,
             provider: deal.provider,
             delivery_hash,
             delivery_content,
+            review_deadline: now + REVIEW_WINDOW_SECS,
         });
 
         Ok(())
@@ -178,48 +501,193 @@ pub mod clawswap {
             caller == deal.client || caller == deal.provider,
             ErrorCode::NotDealParticipant
         );
-        require!(dispute_reason.len() <= 256, ErrorCode::DisputeReasonTooLong);
+        require!(dispute_reason.len() <= MAX_REASON_LEN, ErrorCode::DisputeReasonTooLong);
 
+        let window = ctx.accounts.global.evidence_window_secs;
+        let now = Clock::get()?.unix_timestamp;
         deal.status = DealStatus::Disputed;
         deal.dispute_reason = Some(dispute_reason.clone());
+        deal.evidence_deadline = (window > 0).then_some(now + window);
+
+        let caller_reputation = &mut ctx.accounts.caller_reputation;
+        caller_reputation.ensure_initialized(ctx.accounts.global.key(), caller, ctx.bumps.caller_reputation);
+        caller_reputation.disputes_raised += 1;
 
         emit!(DisputeRaised {
             deal_id: deal.id,
+            milestone: deal.current_milestone,
             raised_by: caller,
             reason: dispute_reason,
+            evidence_deadline: deal.evidence_deadline,
         });
 
         Ok(())
     }
 
     pub fn resolve_dispute(ctx: Context<ResolveDispute>, resolution: DisputeResolution) -> Result<()> {
-        let deal = &mut ctx.accounts.deal;
-        let need = &mut ctx.accounts.need;
         let global = &ctx.accounts.global;
 
-        require!(deal.status == DealStatus::Disputed, ErrorCode::DealNotDisputed);
-        require!(ctx.accounts.authority.key() == global.authority, ErrorCode::NotAuthority);
+        require!(ctx.accounts.deal.status == DealStatus::Disputed, ErrorCode::DealNotDisputed);
+        resolution.validate()?;
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            ctx.accounts.deal.evidence_deadline.is_none_or(|deadline| now > deadline),
+            ErrorCode::EvidenceWindowOpen
+        );
+        // Once a jury is drawn its verdict binds, and anyone may carry it out
+        if ctx.accounts.deal.arbitrated {
+            let jury = ctx.accounts.jury.as_ref().ok_or(ErrorCode::JuryRequired)?;
+            // A jury nobody locked in time never got to vote, so it falls to the authority
+            if jury.locked || now <= jury.lock_deadline {
+                require!(jury.verdict.as_ref() == Some(&resolution), ErrorCode::VerdictMismatch);
+            } else {
+                require!(ctx.accounts.resolver.key() == global.authority, ErrorCode::NotAuthority);
+            }
+        } else {
+            require!(ctx.accounts.resolver.key() == global.authority, ErrorCode::NotAuthority);
+        }
+
+        let escrow = EscrowTokens::load(
+            ctx.accounts.deal.mint,
+            &ctx.accounts.mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.token_program,
+        )?;
 
-        let amount = deal.amount_lamports;
+        let global_key = ctx.accounts.global.key();
+        let (client, provider) = (ctx.accounts.deal.client, ctx.accounts.deal.provider);
+        ctx.accounts.client_reputation.ensure_initialized(global_key, client, ctx.bumps.client_reputation);
+        ctx.accounts.provider_reputation.ensure_initialized(global_key, provider, ctx.bumps.provider_reputation);
 
+        // Disputes cover the current milestone; earlier releases stand
+        let milestone = ctx.accounts.deal.current_milestone;
+        let mut fee = 0;
+        let client_amount;
+        let provider_amount;
         match resolution {
             DisputeResolution::RefundClient => {
-                **deal.to_account_info().lamports.borrow_mut() -= amount;
-                **ctx.accounts.client.lamports.borrow_mut() += amount;
-                deal.status = DealStatus::Cancelled;
-                need.status = NeedStatus::Cancelled;
+                // Refunding cancels the deal, so everything still escrowed goes back
+                let amount = ctx.accounts.deal.remaining_escrow();
+                release_escrow(
+                    &ctx.accounts.deal,
+                    escrow.as_ref(),
+                    &ctx.accounts.client,
+                    ctx.accounts.client_token_account.as_deref(),
+                    amount,
+                )?;
+                ctx.accounts.deal.released_lamports += amount;
+                ctx.accounts.deal.status = DealStatus::Cancelled;
+                ctx.accounts.need.status = NeedStatus::Cancelled;
+                ctx.accounts.provider_reputation.disputes_lost += 1;
+                client_amount = amount;
+                provider_amount = 0;
             }
             DisputeResolution::PayProvider => {
-                **deal.to_account_info().lamports.borrow_mut() -= amount;
-                **ctx.accounts.provider.lamports.borrow_mut() += amount;
-                deal.status = DealStatus::Completed;
-                need.status = NeedStatus::Completed;
+                let amount = ctx.accounts.deal.current_milestone_amount();
+                fee = release_to_provider(
+                    &ctx.accounts.deal,
+                    escrow.as_ref(),
+                    (&ctx.accounts.provider, ctx.accounts.provider_token_account.as_deref()),
+                    (&ctx.accounts.treasury, ctx.accounts.treasury_token_account.as_deref()),
+                    amount,
+                )?;
+                let completed = ctx.accounts.deal.complete_milestone(amount);
+                if completed {
+                    ctx.accounts.need.status = NeedStatus::Completed;
+                }
+                ctx.accounts.client_reputation.disputes_lost += 1;
+                ctx.accounts.client_reputation.record_release(&ctx.accounts.deal, amount, completed);
+                ctx.accounts.provider_reputation.record_release(&ctx.accounts.deal, amount, completed);
+                client_amount = 0;
+                provider_amount = amount - fee;
+            }
+            DisputeResolution::Split { provider_bps } => {
+                // A split settles the deal, dividing everything still escrowed
+                let amount = ctx.accounts.deal.remaining_escrow();
+                let provider_share = (amount as u128 * provider_bps as u128 / BPS_DENOMINATOR as u128) as u64;
+                fee = release_to_provider(
+                    &ctx.accounts.deal,
+                    escrow.as_ref(),
+                    (&ctx.accounts.provider, ctx.accounts.provider_token_account.as_deref()),
+                    (&ctx.accounts.treasury, ctx.accounts.treasury_token_account.as_deref()),
+                    provider_share,
+                )?;
+                release_escrow(
+                    &ctx.accounts.deal,
+                    escrow.as_ref(),
+                    &ctx.accounts.client,
+                    ctx.accounts.client_token_account.as_deref(),
+                    amount - provider_share,
+                )?;
+                ctx.accounts.deal.released_lamports += amount;
+                ctx.accounts.deal.status = DealStatus::PartiallyCompleted;
+                ctx.accounts.need.status = NeedStatus::PartiallyCompleted;
+                ctx.accounts.client_reputation.record_release(&ctx.accounts.deal, provider_share, false);
+                ctx.accounts.provider_reputation.record_release(&ctx.accounts.deal, provider_share, false);
+                client_amount = amount - provider_share;
+                provider_amount = provider_share - fee;
             }
         }
 
+        let deal = &ctx.accounts.deal;
+
         emit!(DisputeResolved {
             deal_id: deal.id,
+            milestone,
             resolution: resolution.clone(),
+            client_amount,
+            provider_amount,
+            fee_lamports: fee,
+        });
+
+        Ok(())
+    }
+
+    /// Appends an entry to the dispute's evidence. Either party may submit up to
+    /// `MAX_EVIDENCE_PER_PARTY` entries while the evidence window is open.
+    pub fn submit_evidence(ctx: Context<SubmitEvidence>, content_hash: String, uri: String) -> Result<()> {
+        let deal = &ctx.accounts.deal;
+        let caller = ctx.accounts.caller.key();
+        let now = Clock::get()?.unix_timestamp;
+
+        require!(deal.status == DealStatus::Disputed, ErrorCode::DealNotDisputed);
+        require!(
+            caller == deal.client || caller == deal.provider,
+            ErrorCode::NotDealParticipant
+        );
+        require!(
+            deal.evidence_deadline.is_none_or(|deadline| now <= deadline),
+            ErrorCode::EvidenceWindowClosed
+        );
+        require!(content_hash.len() <= MAX_DELIVERY_HASH_LEN, ErrorCode::DeliveryHashTooLong);
+        require!(uri.len() <= MAX_EVIDENCE_URI_LEN, ErrorCode::EvidenceUriTooLong);
+
+        let evidence = &mut ctx.accounts.evidence;
+        if evidence.deal == Pubkey::default() {
+            evidence.global = ctx.accounts.global.key();
+            evidence.deal = deal.key();
+            evidence.milestone = deal.current_milestone;
+            evidence.opened_by = caller;
+            evidence.bump = ctx.bumps.evidence;
+        }
+        let submitted = evidence.entries.iter().filter(|entry| entry.submitted_by == caller).count();
+        require!(submitted < MAX_EVIDENCE_PER_PARTY, ErrorCode::TooMuchEvidence);
+
+        evidence.entries.push(EvidenceEntry {
+            submitted_by: caller,
+            content_hash: content_hash.clone(),
+            uri: uri.clone(),
+            submitted_at: now,
+        });
+
+        emit!(EvidenceSubmitted {
+            deal_id: deal.id,
+            milestone: evidence.milestone,
+            index: (evidence.entries.len() - 1) as u8,
+            submitted_by: caller,
+            content_hash,
+            uri,
+            submitted_at: now,
         });
 
         Ok(())
@@ -253,6 +721,151 @@ pub mod clawswap {
         Ok(())
     }
 
+    pub fn update_need(
+        ctx: Context<UpdateNeed>,
+        title: String,
+        description: String,
+        budget_lamports: u64,
+        deadline: Option<i64>,
+    ) -> Result<()> {
+        let need = &mut ctx.accounts.need;
+
+        require!(need.status == NeedStatus::Open, ErrorCode::NeedNotOpen);
+        require!(need.creator == ctx.accounts.creator.key(), ErrorCode::NotNeedCreator);
+        require!(title.len() <= MAX_TITLE_LEN, ErrorCode::TitleTooLong);
+        require!(description.len() <= MAX_DESCRIPTION_LEN, ErrorCode::DescriptionTooLong);
+        require!(
+            ctx.accounts.category.allows_budget(need.mint, budget_lamports),
+            ErrorCode::BelowCategoryMinimum
+        );
+        let now = Clock::get()?.unix_timestamp;
+        require!(deadline.is_none_or(|deadline| deadline > now), ErrorCode::InvalidDeadline);
+
+        need.title = title;
+        need.description = description;
+        need.budget_lamports = budget_lamports;
+        need.deadline = deadline;
+        need.revision += 1;
+
+        emit!(NeedUpdated {
+            id: need.id,
+            revision: need.revision,
+            title: need.title.clone(),
+            budget_lamports: need.budget_lamports,
+            deadline: need.deadline,
+        });
+
+        Ok(())
+    }
+
+    pub fn update_offer(
+        ctx: Context<UpdateOffer>,
+        price_lamports: u64,
+        message: String,
+        delivery_secs: Option<i64>,
+        milestones: Vec<u64>,
+    ) -> Result<()> {
+        let offer = &mut ctx.accounts.offer;
+        let need = &ctx.accounts.need;
+
+        require!(need.status == NeedStatus::Open, ErrorCode::NeedNotOpen);
+        require!(offer.status == OfferStatus::Pending, ErrorCode::OfferNotPending);
+        require!(offer.provider == ctx.accounts.provider.key(), ErrorCode::NotProvider);
+        require!(offer.proposed_by == offer.provider, ErrorCode::CounterPending);
+        require!(message.len() <= MAX_MESSAGE_LEN, ErrorCode::MessageTooLong);
+        require!(need.allows_price(price_lamports), ErrorCode::OverBudget);
+        require!(delivery_secs.is_none_or(|secs| secs > 0), ErrorCode::InvalidDeliveryWindow);
+        validate_milestones(&milestones, price_lamports)?;
+
+        offer.price_lamports = price_lamports;
+        offer.message = message;
+        offer.delivery_secs = delivery_secs;
+        offer.milestones = milestones;
+        offer.revision += 1;
+
+        emit!(OfferUpdated {
+            id: offer.id,
+            need_id: offer.need_id,
+            revision: offer.revision,
+            price_lamports: offer.price_lamports,
+            delivery_secs: offer.delivery_secs,
+            milestones: offer.milestones.clone(),
+        });
+
+        Ok(())
+    }
+
+    /// Proposes a new price on a pending offer, alternating between the need creator and
+    /// the provider. Milestones are dropped, so a countered offer pays out in one go.
+    pub fn counter_offer(ctx: Context<CounterOffer>, price_lamports: u64) -> Result<()> {
+        let offer = &mut ctx.accounts.offer;
+        let need = &ctx.accounts.need;
+        let caller = ctx.accounts.caller.key();
+
+        require!(!ctx.accounts.global.marketplace_paused, ErrorCode::Paused);
+        require!(need.status == NeedStatus::Open, ErrorCode::NeedNotOpen);
+        require!(offer.status == OfferStatus::Pending, ErrorCode::OfferNotPending);
+        require!(caller == need.creator || caller == offer.provider, ErrorCode::NotOfferParticipant);
+        require!(caller != offer.proposed_by, ErrorCode::CannotCounterOwnProposal);
+        require!(offer.negotiation_round < MAX_NEGOTIATION_ROUNDS, ErrorCode::TooManyRounds);
+        require!(need.allows_price(price_lamports), ErrorCode::OverBudget);
+
+        offer.price_lamports = price_lamports;
+        offer.milestones = Vec::new();
+        offer.proposed_by = caller;
+        offer.negotiation_round += 1;
+        offer.revision += 1;
+
+        emit!(OfferCountered {
+            id: offer.id,
+            need_id: offer.need_id,
+            round: offer.negotiation_round,
+            proposed_by: caller,
+            price_lamports,
+        });
+
+        Ok(())
+    }
+
+    /// The provider agrees to the need creator's counter, which the creator can then accept
+    pub fn accept_counter_offer(ctx: Context<AcceptCounterOffer>) -> Result<()> {
+        let offer = &mut ctx.accounts.offer;
+
+        require!(!ctx.accounts.global.marketplace_paused, ErrorCode::Paused);
+        require!(offer.status == OfferStatus::Pending, ErrorCode::OfferNotPending);
+        require!(offer.provider == ctx.accounts.provider.key(), ErrorCode::NotProvider);
+        require!(offer.proposed_by != offer.provider, ErrorCode::NoCounterToAccept);
+
+        offer.proposed_by = offer.provider;
+
+        emit!(CounterOfferAccepted {
+            id: offer.id,
+            round: offer.negotiation_round,
+            price_lamports: offer.price_lamports,
+        });
+
+        Ok(())
+    }
+
+    pub fn reject_offer(ctx: Context<RejectOffer>) -> Result<()> {
+        let offer = &mut ctx.accounts.offer;
+        let need = &ctx.accounts.need;
+
+        require!(offer.status == OfferStatus::Pending, ErrorCode::OfferNotPending);
+        require!(need.creator == ctx.accounts.creator.key(), ErrorCode::NotNeedCreator);
+        require!(offer.need_id == need.id, ErrorCode::OfferNeedMismatch);
+
+        offer.status = OfferStatus::Rejected;
+
+        emit!(OfferRejected {
+            id: offer.id,
+            need_id: offer.need_id,
+            provider: offer.provider,
+        });
+
+        Ok(())
+    }
+
     // ── Barter Instructions ──
 
     pub fn create_barter(
@@ -260,20 +873,41 @@ pub mod clawswap {
         what_i_offer: String,
         what_i_want: String,
         target_agent: Option<Pubkey>,
+        collateral_lamports: u64,
+        accept_by: Option<i64>,
+        deliver_by: Option<i64>,
     ) -> Result<()> {
-        require!(what_i_offer.len() <= 256, ErrorCode::BarterOfferTooLong);
-        require!(what_i_want.len() <= 256, ErrorCode::BarterWantTooLong);
+        require!(what_i_offer.len() <= MAX_BARTER_TERMS_LEN, ErrorCode::BarterOfferTooLong);
+        require!(what_i_want.len() <= MAX_BARTER_TERMS_LEN, ErrorCode::BarterWantTooLong);
+        require!(
+            collateral_lamports > 0 || ctx.accounts.mint.is_none(),
+            ErrorCode::InvalidCollateral
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        require!(accept_by.is_none_or(|accept_by| accept_by > now), ErrorCode::InvalidBarterDeadline);
+        require!(
+            deliver_by.is_none_or(|deliver_by| deliver_by > accept_by.unwrap_or(now)),
+            ErrorCode::InvalidBarterDeadline
+        );
 
         let global = &mut ctx.accounts.global;
         let barter = &mut ctx.accounts.barter;
 
+        require!(!global.barter_paused, ErrorCode::Paused);
+
         barter.id = global.barter_counter;
+        barter.global = global.key();
         barter.initiator = ctx.accounts.initiator.key();
-        barter.counterpart = target_agent.unwrap_or(Pubkey::default());
+        barter.counterpart = target_agent.unwrap_or_default();
         barter.what_i_offer = what_i_offer.clone();
         barter.what_i_want = what_i_want.clone();
+        barter.collateral_lamports = collateral_lamports;
+        barter.collateral_mint = ctx.accounts.mint.as_ref().map(|mint| mint.key());
         barter.status = BarterStatus::Open;
-        barter.created_at = Clock::get()?.unix_timestamp;
+        barter.created_at = now;
+        barter.accept_by = accept_by;
+        barter.deliver_by = deliver_by;
         barter.side_a_delivery = None;
         barter.side_a_hash = None;
         barter.side_a_confirmed = false;
@@ -281,15 +915,34 @@ pub mod clawswap {
         barter.side_b_hash = None;
         barter.side_b_confirmed = false;
         barter.dispute_reason = None;
+        barter.arbiter = None;
+        barter.negotiation_round = 0;
+        barter.proposed_by = barter.initiator;
         barter.bump = ctx.bumps.barter;
 
         global.barter_counter += 1;
 
+        let escrow = EscrowTokens::load(
+            barter.collateral_mint,
+            &ctx.accounts.mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.token_program,
+        )?;
+        post_collateral(
+            &ctx.accounts.barter,
+            escrow.as_ref(),
+            &ctx.accounts.initiator,
+            ctx.accounts.initiator_token_account.as_deref(),
+            &ctx.accounts.system_program,
+        )?;
+
         emit!(BarterCreated {
-            id: barter.id,
-            initiator: barter.initiator,
+            id: ctx.accounts.barter.id,
+            initiator: ctx.accounts.barter.initiator,
             what_i_offer,
             what_i_want,
+            collateral_lamports,
+            collateral_mint: ctx.accounts.barter.collateral_mint,
         });
 
         Ok(())
@@ -299,8 +952,14 @@ pub mod clawswap {
         let barter = &mut ctx.accounts.barter;
         let caller = ctx.accounts.caller.key();
 
+        require!(!ctx.accounts.global.barter_paused, ErrorCode::Paused);
         require!(barter.status == BarterStatus::Open, ErrorCode::BarterNotOpen);
         require!(caller != barter.initiator, ErrorCode::CannotAcceptOwnBarter);
+        require!(barter.proposed_by == barter.initiator, ErrorCode::CounterPending);
+        let now = Clock::get()?.unix_timestamp;
+        require!(barter.accept_by.is_none_or(|accept_by| now <= accept_by), ErrorCode::BarterExpired);
+        // Accepting after the delivery deadline would only start a barter that's already expirable
+        require!(barter.deliver_by.is_none_or(|deliver_by| now <= deliver_by), ErrorCode::DeliveryDeadlinePassed);
 
         if barter.counterpart != Pubkey::default() {
             require!(caller == barter.counterpart, ErrorCode::WrongBarterTarget);
@@ -310,46 +969,121 @@ pub mod clawswap {
 
         barter.status = BarterStatus::InProgress;
 
+        // The counterpart matches the initiator's stake
+        let escrow = EscrowTokens::load(
+            ctx.accounts.barter.collateral_mint,
+            &ctx.accounts.mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.token_program,
+        )?;
+        post_collateral(
+            &ctx.accounts.barter,
+            escrow.as_ref(),
+            &ctx.accounts.caller,
+            ctx.accounts.caller_token_account.as_deref(),
+            &ctx.accounts.system_program,
+        )?;
+
         emit!(BarterAccepted {
-            id: barter.id,
-            counterpart: barter.counterpart,
+            id: ctx.accounts.barter.id,
+            counterpart: ctx.accounts.barter.counterpart,
         });
 
         Ok(())
     }
 
-    pub fn submit_barter_delivery(
-        ctx: Context<SubmitBarterDelivery>,
-        delivery_content: String,
-        delivery_hash: String,
-    ) -> Result<()> {
+    /// Proposes new terms on an open barter, alternating between the initiator and a
+    /// counterpart. Terms are always from the initiator's side. Anyone may answer the
+    /// initiator on an untargeted barter; it stays unbound until someone accepts it.
+    pub fn counter_barter(ctx: Context<CounterBarter>, what_i_offer: String, what_i_want: String) -> Result<()> {
         let barter = &mut ctx.accounts.barter;
         let caller = ctx.accounts.caller.key();
 
-        require!(barter.status == BarterStatus::InProgress, ErrorCode::BarterNotInProgress);
-        require!(
-            caller == barter.initiator || caller == barter.counterpart,
-            ErrorCode::NotBarterParticipant
-        );
-        require!(delivery_content.len() <= 512, ErrorCode::DeliveryContentTooLong);
-        require!(delivery_hash.len() <= 64, ErrorCode::DeliveryContentTooLong);
-
-        let side: String;
-        if caller == barter.initiator {
-            barter.side_a_delivery = Some(delivery_content);
-            barter.side_a_hash = Some(delivery_hash.clone());
-            side = "A".to_string();
-        } else {
-            barter.side_b_delivery = Some(delivery_content);
-            barter.side_b_hash = Some(delivery_hash.clone());
-            side = "B".to_string();
+        require!(!ctx.accounts.global.barter_paused, ErrorCode::Paused);
+        require!(barter.status == BarterStatus::Open, ErrorCode::BarterNotOpen);
+        require!(what_i_offer.len() <= MAX_BARTER_TERMS_LEN, ErrorCode::BarterOfferTooLong);
+        require!(what_i_want.len() <= MAX_BARTER_TERMS_LEN, ErrorCode::BarterWantTooLong);
+        require!(caller != barter.proposed_by, ErrorCode::CannotCounterOwnProposal);
+        require!(barter.negotiation_round < MAX_NEGOTIATION_ROUNDS, ErrorCode::TooManyRounds);
+        if caller != barter.initiator {
+            require!(
+                barter.counterpart == Pubkey::default() || barter.counterpart == caller,
+                ErrorCode::WrongBarterTarget
+            );
+            // Counterparts answer the initiator, not each other
+            require!(barter.proposed_by == barter.initiator, ErrorCode::CounterPending);
         }
 
-        emit!(BarterDeliverySubmitted {
-            barter_id: barter.id,
-            side,
-            delivery_hash,
-        });
+        barter.what_i_offer = what_i_offer.clone();
+        barter.what_i_want = what_i_want.clone();
+        barter.proposed_by = caller;
+        barter.negotiation_round += 1;
+
+        emit!(BarterCountered {
+            id: barter.id,
+            round: barter.negotiation_round,
+            proposed_by: caller,
+            what_i_offer,
+            what_i_want,
+        });
+
+        Ok(())
+    }
+
+    /// The initiator agrees to the pending counter, which can then be accepted like the
+    /// original terms
+    pub fn accept_counter_barter(ctx: Context<AcceptCounterBarter>) -> Result<()> {
+        let barter = &mut ctx.accounts.barter;
+
+        require!(!ctx.accounts.global.barter_paused, ErrorCode::Paused);
+        require!(barter.status == BarterStatus::Open, ErrorCode::BarterNotOpen);
+        require!(ctx.accounts.initiator.key() == barter.initiator, ErrorCode::BarterNotInitiator);
+        require!(barter.proposed_by != barter.initiator, ErrorCode::NoCounterToAccept);
+
+        barter.proposed_by = barter.initiator;
+
+        emit!(BarterCounterAccepted {
+            id: barter.id,
+            round: barter.negotiation_round,
+        });
+
+        Ok(())
+    }
+
+    pub fn submit_barter_delivery(
+        ctx: Context<SubmitBarterDelivery>,
+        delivery_content: String,
+        delivery_hash: String,
+    ) -> Result<()> {
+        let barter = &mut ctx.accounts.barter;
+        let caller = ctx.accounts.caller.key();
+
+        require!(barter.status == BarterStatus::InProgress, ErrorCode::BarterNotInProgress);
+        require!(
+            caller == barter.initiator || caller == barter.counterpart,
+            ErrorCode::NotBarterParticipant
+        );
+        require!(delivery_content.len() <= MAX_DELIVERY_CONTENT_LEN, ErrorCode::DeliveryContentTooLong);
+        require!(delivery_hash.len() <= MAX_DELIVERY_HASH_LEN, ErrorCode::DeliveryHashTooLong);
+        let now = Clock::get()?.unix_timestamp;
+        require!(barter.deliver_by.is_none_or(|deliver_by| now <= deliver_by), ErrorCode::DeliveryDeadlinePassed);
+
+        let side: String;
+        if caller == barter.initiator {
+            barter.side_a_delivery = Some(delivery_content);
+            barter.side_a_hash = Some(delivery_hash.clone());
+            side = "A".to_string();
+        } else {
+            barter.side_b_delivery = Some(delivery_content);
+            barter.side_b_hash = Some(delivery_hash.clone());
+            side = "B".to_string();
+        }
+
+        emit!(BarterDeliverySubmitted {
+            barter_id: barter.id,
+            side,
+            delivery_hash,
+        });
 
         Ok(())
     }
@@ -381,7 +1115,31 @@ pub mod clawswap {
 
         if barter.side_a_confirmed && barter.side_b_confirmed {
             barter.status = BarterStatus::Completed;
-            emit!(BarterCompleted { id: barter.id });
+
+            let global_key = ctx.accounts.global.key();
+            let initiator_reputation = &mut ctx.accounts.initiator_reputation;
+            initiator_reputation.ensure_initialized(global_key, barter.initiator, ctx.bumps.initiator_reputation);
+            initiator_reputation.completed_barters += 1;
+            let counterpart_reputation = &mut ctx.accounts.counterpart_reputation;
+            counterpart_reputation.ensure_initialized(global_key, barter.counterpart, ctx.bumps.counterpart_reputation);
+            counterpart_reputation.completed_barters += 1;
+
+            // Both sides delivered, so both stakes go back
+            let escrow = EscrowTokens::load(
+                ctx.accounts.barter.collateral_mint,
+                &ctx.accounts.mint,
+                &ctx.accounts.vault,
+                &ctx.accounts.token_program,
+            )?;
+            release_collateral(
+                &ctx.accounts.barter,
+                escrow.as_ref(),
+                (&ctx.accounts.initiator, ctx.accounts.initiator_token_account.as_deref()),
+                (&ctx.accounts.counterpart, ctx.accounts.counterpart_token_account.as_deref()),
+                BPS_DENOMINATOR / 2,
+            )?;
+
+            emit!(BarterCompleted { id: ctx.accounts.barter.id });
         }
 
         Ok(())
@@ -394,7 +1152,22 @@ pub mod clawswap {
 
         barter.status = BarterStatus::Cancelled;
 
-        emit!(BarterCancelled { id: barter.id });
+        // Only the initiator has staked while the barter is open
+        let escrow = EscrowTokens::load(
+            ctx.accounts.barter.collateral_mint,
+            &ctx.accounts.mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.token_program,
+        )?;
+        release_collateral(
+            &ctx.accounts.barter,
+            escrow.as_ref(),
+            (&ctx.accounts.initiator, ctx.accounts.initiator_token_account.as_deref()),
+            (&ctx.accounts.initiator, ctx.accounts.initiator_token_account.as_deref()),
+            BPS_DENOMINATOR,
+        )?;
+
+        emit!(BarterCancelled { id: ctx.accounts.barter.id });
 
         Ok(())
     }
@@ -408,11 +1181,15 @@ pub mod clawswap {
             caller == barter.initiator || caller == barter.counterpart,
             ErrorCode::NotBarterParticipant
         );
-        require!(reason.len() <= 256, ErrorCode::DisputeReasonTooLong);
+        require!(reason.len() <= MAX_REASON_LEN, ErrorCode::DisputeReasonTooLong);
 
         barter.status = BarterStatus::Disputed;
         barter.dispute_reason = Some(reason.clone());
 
+        let caller_reputation = &mut ctx.accounts.caller_reputation;
+        caller_reputation.ensure_initialized(ctx.accounts.global.key(), caller, ctx.bumps.caller_reputation);
+        caller_reputation.disputes_raised += 1;
+
         emit!(BarterDisputed {
             id: barter.id,
             raised_by: caller,
@@ -422,304 +1199,3246 @@ pub mod clawswap {
         Ok(())
     }
 
+    pub fn assign_barter_arbiter(ctx: Context<AssignBarterArbiter>, arbiter: Pubkey) -> Result<()> {
+        let barter = &mut ctx.accounts.barter;
+
+        require!(ctx.accounts.authority.key() == ctx.accounts.global.authority, ErrorCode::NotAuthority);
+        require!(barter.status == BarterStatus::Disputed, ErrorCode::BarterNotDisputed);
+
+        barter.arbiter = Some(arbiter);
+
+        emit!(BarterArbiterAssigned {
+            id: barter.id,
+            arbiter,
+        });
+
+        Ok(())
+    }
+
+    pub fn resolve_barter_dispute(ctx: Context<ResolveBarterDispute>, resolution: BarterResolution) -> Result<()> {
+        let barter = &ctx.accounts.barter;
+        let resolver = ctx.accounts.resolver.key();
+
+        require!(barter.status == BarterStatus::Disputed, ErrorCode::BarterNotDisputed);
+        require!(
+            resolver == ctx.accounts.global.authority || barter.arbiter == Some(resolver),
+            ErrorCode::NotBarterResolver
+        );
+
+        // Collateral share for the initiator, if the barter settles, and the status it ends in
+        let (initiator_bps, status) = match resolution {
+            BarterResolution::Cancel => (Some(BPS_DENOMINATOR / 2), BarterStatus::Cancelled),
+            BarterResolution::Complete => (Some(BPS_DENOMINATOR / 2), BarterStatus::Completed),
+            BarterResolution::Slash { side: BarterSide::A } => (Some(0), BarterStatus::Cancelled),
+            BarterResolution::Slash { side: BarterSide::B } => (Some(BPS_DENOMINATOR), BarterStatus::Cancelled),
+            BarterResolution::Split { initiator_bps } => {
+                require!(initiator_bps as u64 <= BPS_DENOMINATOR, ErrorCode::InvalidSplit);
+                (Some(initiator_bps as u64), BarterStatus::Cancelled)
+            }
+            // Confirms one side on its behalf; the barter completes if the other is already confirmed
+            BarterResolution::SideFulfilled { ref side } => {
+                let (delivered, other_confirmed) = match side {
+                    BarterSide::A => (barter.side_a_delivery.is_some(), barter.side_b_confirmed),
+                    BarterSide::B => (barter.side_b_delivery.is_some(), barter.side_a_confirmed),
+                };
+                require!(delivered, ErrorCode::DeliveryNotReady);
+                if other_confirmed {
+                    (Some(BPS_DENOMINATOR / 2), BarterStatus::Completed)
+                } else {
+                    (None, BarterStatus::InProgress)
+                }
+            }
+        };
+
+        let (mut initiator_amount, mut counterpart_amount) = (0, 0);
+        if let Some(initiator_bps) = initiator_bps {
+            let escrow = EscrowTokens::load(
+                barter.collateral_mint,
+                &ctx.accounts.mint,
+                &ctx.accounts.vault,
+                &ctx.accounts.token_program,
+            )?;
+            (initiator_amount, counterpart_amount) = release_collateral(
+                barter,
+                escrow.as_ref(),
+                (&ctx.accounts.initiator, ctx.accounts.initiator_token_account.as_deref()),
+                (&ctx.accounts.counterpart, ctx.accounts.counterpart_token_account.as_deref()),
+                initiator_bps,
+            )?;
+        }
+
+        let barter = &mut ctx.accounts.barter;
+        match resolution {
+            BarterResolution::Complete => {
+                barter.side_a_confirmed = true;
+                barter.side_b_confirmed = true;
+            }
+            BarterResolution::SideFulfilled { side: BarterSide::A } => barter.side_a_confirmed = true,
+            BarterResolution::SideFulfilled { side: BarterSide::B } => barter.side_b_confirmed = true,
+            _ => {}
+        }
+        barter.status = status.clone();
+
+        let global_key = ctx.accounts.global.key();
+        let initiator_reputation = &mut ctx.accounts.initiator_reputation;
+        initiator_reputation.ensure_initialized(global_key, barter.initiator, ctx.bumps.initiator_reputation);
+        let counterpart_reputation = &mut ctx.accounts.counterpart_reputation;
+        counterpart_reputation.ensure_initialized(global_key, barter.counterpart, ctx.bumps.counterpart_reputation);
+        match resolution {
+            BarterResolution::Slash { side: BarterSide::A } => initiator_reputation.disputes_lost += 1,
+            BarterResolution::Slash { side: BarterSide::B } => counterpart_reputation.disputes_lost += 1,
+            _ if status == BarterStatus::Completed => {
+                initiator_reputation.completed_barters += 1;
+                counterpart_reputation.completed_barters += 1;
+            }
+            _ => {}
+        }
+
+        emit!(BarterDisputeResolved {
+            id: barter.id,
+            resolved_by: resolver,
+            resolution,
+            status,
+            initiator_amount,
+            counterpart_amount,
+        });
+
+        Ok(())
+    }
+
     pub fn confirm_delivery(ctx: Context<ConfirmDelivery>) -> Result<()> {
+        require!(ctx.accounts.deal.status == DealStatus::DeliverySubmitted, ErrorCode::DeliveryNotSubmitted);
+        require!(ctx.accounts.deal.client == ctx.accounts.client.key(), ErrorCode::NotClient);
+
+        // Transfer the current milestone from escrow to provider
+        let milestone = ctx.accounts.deal.current_milestone;
+        let amount = ctx.accounts.deal.current_milestone_amount();
+        let escrow = EscrowTokens::load(
+            ctx.accounts.deal.mint,
+            &ctx.accounts.mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.token_program,
+        )?;
+        let fee = release_to_provider(
+            &ctx.accounts.deal,
+            escrow.as_ref(),
+            (&ctx.accounts.provider, ctx.accounts.provider_token_account.as_deref()),
+            (&ctx.accounts.treasury, ctx.accounts.treasury_token_account.as_deref()),
+            amount,
+        )?;
+
         let deal = &mut ctx.accounts.deal;
         let need = &mut ctx.accounts.need;
+        let completed = deal.complete_milestone(amount);
+        if completed {
+            need.status = NeedStatus::Completed;
+        }
+
+        let global_key = ctx.accounts.global.key();
+        let client_reputation = &mut ctx.accounts.client_reputation;
+        client_reputation.ensure_initialized(global_key, deal.client, ctx.bumps.client_reputation);
+        client_reputation.record_release(deal, amount, completed);
+        let provider_reputation = &mut ctx.accounts.provider_reputation;
+        provider_reputation.ensure_initialized(global_key, deal.provider, ctx.bumps.provider_reputation);
+        provider_reputation.record_release(deal, amount, completed);
+
+        emit!(DeliveryConfirmed {
+            deal_id: deal.id,
+            milestone,
+            client: deal.client,
+            provider: deal.provider,
+            amount_lamports: amount,
+            fee_lamports: fee,
+        });
 
-        require!(deal.status == DealStatus::DeliverySubmitted, ErrorCode::DeliveryNotSubmitted);
-        require!(deal.client == ctx.accounts.client.key(), ErrorCode::NotClient);
+        Ok(())
+    }
 
-        // Transfer escrowed amount from deal PDA to provider
-        let amount = deal.amount_lamports;
-        **deal.to_account_info().lamports.borrow_mut() -= amount;
-        **ctx.accounts.provider.lamports.borrow_mut() += amount;
+    // ── Deal Timeouts ──
 
-        deal.status = DealStatus::Completed;
-        need.status = NeedStatus::Completed;
+    pub fn release_expired_deal(ctx: Context<ReleaseExpiredDeal>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
 
-        emit!(DeliveryConfirmed {
+        require!(ctx.accounts.deal.status == DealStatus::DeliverySubmitted, ErrorCode::DeliveryNotSubmitted);
+        require!(
+            ctx.accounts.deal.review_deadline.is_some_and(|deadline| now > deadline),
+            ErrorCode::ReviewWindowOpen
+        );
+
+        let milestone = ctx.accounts.deal.current_milestone;
+        let amount = ctx.accounts.deal.current_milestone_amount();
+        let escrow = EscrowTokens::load(
+            ctx.accounts.deal.mint,
+            &ctx.accounts.mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.token_program,
+        )?;
+        let fee = release_to_provider(
+            &ctx.accounts.deal,
+            escrow.as_ref(),
+            (&ctx.accounts.provider, ctx.accounts.provider_token_account.as_deref()),
+            (&ctx.accounts.treasury, ctx.accounts.treasury_token_account.as_deref()),
+            amount,
+        )?;
+
+        let deal = &mut ctx.accounts.deal;
+        let need = &mut ctx.accounts.need;
+        let completed = deal.complete_milestone(amount);
+        if completed {
+            need.status = NeedStatus::Completed;
+        }
+
+        let global_key = ctx.accounts.global.key();
+        let client_reputation = &mut ctx.accounts.client_reputation;
+        client_reputation.ensure_initialized(global_key, deal.client, ctx.bumps.client_reputation);
+        client_reputation.record_release(deal, amount, completed);
+        let provider_reputation = &mut ctx.accounts.provider_reputation;
+        provider_reputation.ensure_initialized(global_key, deal.provider, ctx.bumps.provider_reputation);
+        provider_reputation.record_release(deal, amount, completed);
+
+        emit!(DealAutoReleased {
+            deal_id: deal.id,
+            milestone,
+            provider: deal.provider,
+            amount_lamports: amount,
+            fee_lamports: fee,
+            cranked_by: ctx.accounts.caller.key(),
+        });
+
+        Ok(())
+    }
+
+    pub fn refund_expired_deal(ctx: Context<RefundExpiredDeal>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+
+        require!(ctx.accounts.deal.status == DealStatus::InProgress, ErrorCode::DealNotInProgress);
+        require!(
+            ctx.accounts.deal.delivery_deadline.is_some_and(|deadline| now > deadline),
+            ErrorCode::DeliveryDeadlineNotReached
+        );
+
+        // Milestones already released stay with the provider
+        let amount = ctx.accounts.deal.remaining_escrow();
+        let escrow = EscrowTokens::load(
+            ctx.accounts.deal.mint,
+            &ctx.accounts.mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.token_program,
+        )?;
+        release_escrow(
+            &ctx.accounts.deal,
+            escrow.as_ref(),
+            &ctx.accounts.client,
+            ctx.accounts.client_token_account.as_deref(),
+            amount,
+        )?;
+
+        let deal = &mut ctx.accounts.deal;
+        let need = &mut ctx.accounts.need;
+        deal.released_lamports += amount;
+        deal.status = DealStatus::Cancelled;
+        need.status = NeedStatus::Cancelled;
+
+        emit!(DealAutoRefunded {
             deal_id: deal.id,
             client: deal.client,
+            amount_lamports: amount,
+            cranked_by: ctx.accounts.caller.key(),
+        });
+
+        Ok(())
+    }
+
+    // ── Deal Cancellation ──
+
+    /// Either party proposes cancelling an in-progress deal: the client is refunded whatever
+    /// is still escrowed, less `kill_fee_lamports` paid to the provider. The other party
+    /// calling with the same kill fee carries it out; a different fee replaces the proposal.
+    /// The kill fee is agreed between the parties, so it is paid in full, without a protocol fee.
+    /// `reopen_need` is the client's choice, taken from whichever call they make; see
+    /// `Need::reopen` for what a reopened need carries over.
+    pub fn mutual_cancel(ctx: Context<MutualCancel>, kill_fee_lamports: u64, reopen_need: bool) -> Result<()> {
+        let caller = ctx.accounts.caller.key();
+        let deal = &ctx.accounts.deal;
+
+        require!(
+            deal.status == DealStatus::InProgress || deal.status == DealStatus::DeliverySubmitted,
+            ErrorCode::DealNotCancellable
+        );
+        require!(
+            caller == deal.client || caller == deal.provider,
+            ErrorCode::NotDealParticipant
+        );
+        let amount = deal.remaining_escrow();
+        require!(kill_fee_lamports <= amount, ErrorCode::InvalidKillFee);
+
+        let agreed = deal.cancel_proposal.as_ref().filter(|proposal| {
+            proposal.proposed_by != caller && proposal.kill_fee_lamports == kill_fee_lamports
+        });
+        let Some(proposal) = agreed else {
+            ctx.accounts.deal.cancel_proposal = Some(CancelProposal {
+                proposed_by: caller,
+                kill_fee_lamports,
+                reopen_need,
+            });
+
+            emit!(MutualCancelProposed {
+                deal_id: ctx.accounts.deal.id,
+                proposed_by: caller,
+                kill_fee_lamports,
+                reopen_need,
+            });
+
+            return Ok(());
+        };
+        let reopen_need = if caller == deal.client { reopen_need } else { proposal.reopen_need };
+
+        let escrow = EscrowTokens::load(
+            ctx.accounts.deal.mint,
+            &ctx.accounts.mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.token_program,
+        )?;
+        release_escrow(
+            &ctx.accounts.deal,
+            escrow.as_ref(),
+            &ctx.accounts.provider,
+            ctx.accounts.provider_token_account.as_deref(),
+            kill_fee_lamports,
+        )?;
+        release_escrow(
+            &ctx.accounts.deal,
+            escrow.as_ref(),
+            &ctx.accounts.client,
+            ctx.accounts.client_token_account.as_deref(),
+            amount - kill_fee_lamports,
+        )?;
+
+        let deal = &mut ctx.accounts.deal;
+        let need = &mut ctx.accounts.need;
+        if reopen_need {
+            need.reopen(deal.released_lamports);
+        } else {
+            need.status = NeedStatus::Cancelled;
+        }
+        deal.released_lamports += amount;
+        deal.status = DealStatus::Cancelled;
+        deal.cancel_proposal = None;
+
+        emit!(DealMutuallyCancelled {
+            deal_id: deal.id,
+            refunded_lamports: amount - kill_fee_lamports,
+            kill_fee_lamports,
+            need_reopened: reopen_need,
+        });
+
+        Ok(())
+    }
+
+    /// The provider backs out of a deal: everything still escrowed goes back to the
+    /// client and the need reopens, as `Need::reopen` describes. Counts against the provider.
+    pub fn withdraw_from_deal(ctx: Context<WithdrawFromDeal>) -> Result<()> {
+        require!(
+            ctx.accounts.deal.status == DealStatus::InProgress
+                || ctx.accounts.deal.status == DealStatus::DeliverySubmitted,
+            ErrorCode::DealNotCancellable
+        );
+
+        // Milestones already released stay with the provider
+        let amount = ctx.accounts.deal.remaining_escrow();
+        let escrow = EscrowTokens::load(
+            ctx.accounts.deal.mint,
+            &ctx.accounts.mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.token_program,
+        )?;
+        release_escrow(
+            &ctx.accounts.deal,
+            escrow.as_ref(),
+            &ctx.accounts.client,
+            ctx.accounts.client_token_account.as_deref(),
+            amount,
+        )?;
+
+        let deal = &mut ctx.accounts.deal;
+        let need = &mut ctx.accounts.need;
+        need.reopen(deal.released_lamports);
+        deal.released_lamports += amount;
+        deal.status = DealStatus::Cancelled;
+        deal.cancel_proposal = None;
+
+        let provider_reputation = &mut ctx.accounts.provider_reputation;
+        provider_reputation.ensure_initialized(ctx.accounts.global.key(), deal.provider, ctx.bumps.provider_reputation);
+        provider_reputation.withdrawals += 1;
+
+        emit!(DealWithdrawn {
+            deal_id: deal.id,
             provider: deal.provider,
-            amount_lamports: deal.amount_lamports,
+            refunded_lamports: amount,
         });
 
         Ok(())
     }
-}
 
-// Accounts structs
-#[derive(Accounts)]
-#[instruction(global_id: u64)]
-pub struct Initialize<'info> {
-    #[account(
-        init,
-        payer = authority,
-        space = Global::SIZE,
-        seeds = [b"global", global_id.to_le_bytes().as_ref()],
-        bump
-    )]
-    pub global: Account<'info, Global>,
-    
-    #[account(mut)]
-    pub authority: Signer<'info>,
-    pub system_program: Program<'info, System>,
-}
+    // ── Barter Timeouts ──
 
-#[derive(Accounts)]
-pub struct CreateNeed<'info> {
-    #[account(mut)]
-    pub global: Account<'info, Global>,
+    pub fn expire_barter(ctx: Context<ExpireBarter>) -> Result<()> {
+        let barter = &ctx.accounts.barter;
+        let now = Clock::get()?.unix_timestamp;
 
-    #[account(
-        init,
-        payer = creator,
-        space = Need::SIZE,
-        seeds = [b"need", global.need_counter.to_le_bytes().as_ref()],
-        bump
+        // Open barters stake only the initiator; in-progress ones forfeit the stake of a
+        // side that never delivered to a side that did, and otherwise return both
+        let initiator_bps = match barter.status {
+            BarterStatus::Open => {
+                require!(barter.accept_by.is_some_and(|accept_by| now > accept_by), ErrorCode::BarterNotExpired);
+                BPS_DENOMINATOR
+            }
+            BarterStatus::InProgress => {
+                require!(barter.deliver_by.is_some_and(|deliver_by| now > deliver_by), ErrorCode::BarterNotExpired);
+                match (barter.side_a_delivery.is_some(), barter.side_b_delivery.is_some()) {
+                    (true, false) => BPS_DENOMINATOR,
+                    (false, true) => 0,
+                    _ => BPS_DENOMINATOR / 2,
+                }
+            }
+            _ => return err!(ErrorCode::BarterNotExpired),
+        };
+
+        let escrow = EscrowTokens::load(
+            barter.collateral_mint,
+            &ctx.accounts.mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.token_program,
+        )?;
+        let (initiator_amount, counterpart_amount) = release_collateral(
+            barter,
+            escrow.as_ref(),
+            (&ctx.accounts.initiator, ctx.accounts.initiator_token_account.as_deref()),
+            (&ctx.accounts.counterpart, ctx.accounts.counterpart_token_account.as_deref()),
+            initiator_bps,
+        )?;
+
+        let barter = &mut ctx.accounts.barter;
+        let previous_status = barter.status.clone();
+        barter.status = BarterStatus::Expired;
+
+        emit!(BarterExpired {
+            id: barter.id,
+            previous_status,
+            initiator_amount,
+            counterpart_amount,
+        });
+
+        Ok(())
+    }
+
+    // ── Barter Rings ──
+
+    /// Every participant has to deliver by `deliver_by`; after that anyone can expire the ring.
+    pub fn create_barter_ring(ctx: Context<CreateBarterRing>, participants: Vec<RingTerms>, deliver_by: i64) -> Result<()> {
+        let global = &mut ctx.accounts.global;
+        let ring = &mut ctx.accounts.ring;
+        let initiator = ctx.accounts.initiator.key();
+        let now = Clock::get()?.unix_timestamp;
+
+        require!(!global.barter_paused, ErrorCode::Paused);
+        require!(deliver_by > now, ErrorCode::InvalidBarterDeadline);
+        require!(
+            (3..=MAX_RING_PARTICIPANTS).contains(&participants.len()),
+            ErrorCode::InvalidRingSize
+        );
+        require!(participants[0].wallet == initiator, ErrorCode::RingInitiatorNotFirst);
+        for (i, terms) in participants.iter().enumerate() {
+            require!(
+                terms.gives.len() <= MAX_RING_TERMS_LEN && terms.gets.len() <= MAX_RING_TERMS_LEN,
+                ErrorCode::RingTermsTooLong
+            );
+            require!(
+                participants[..i].iter().all(|other| other.wallet != terms.wallet),
+                ErrorCode::DuplicateRingParticipant
+            );
+        }
+
+        ring.id = global.ring_counter;
+        ring.global = global.key();
+        ring.initiator = initiator;
+        ring.participants = participants
+            .into_iter()
+            .map(|terms| RingParticipant {
+                joined: terms.wallet == initiator,
+                wallet: terms.wallet,
+                gives: terms.gives,
+                gets: terms.gets,
+                delivery_hash: None,
+                confirmed: false,
+            })
+            .collect();
+        ring.status = BarterStatus::Open;
+        ring.created_at = now;
+        ring.deliver_by = deliver_by;
+        ring.bump = ctx.bumps.ring;
+
+        global.ring_counter += 1;
+
+        emit!(BarterRingCreated {
+            id: ring.id,
+            initiator,
+            participants: ring.participants.iter().map(|participant| participant.wallet).collect(),
+            deliver_by,
+        });
+
+        Ok(())
+    }
+
+    pub fn join_barter_ring(ctx: Context<JoinBarterRing>) -> Result<()> {
+        let ring = &mut ctx.accounts.ring;
+        let caller = ctx.accounts.caller.key();
+
+        require!(!ctx.accounts.global.barter_paused, ErrorCode::Paused);
+        require!(ring.status == BarterStatus::Open, ErrorCode::BarterNotOpen);
+        let index = ring.position_of(&caller).ok_or(ErrorCode::NotBarterParticipant)?;
+        require!(!ring.participants[index].joined, ErrorCode::AlreadyJoinedRing);
+
+        ring.participants[index].joined = true;
+
+        emit!(BarterRingJoined {
+            id: ring.id,
+            participant: caller,
+        });
+
+        if ring.participants.iter().all(|participant| participant.joined) {
+            ring.status = BarterStatus::InProgress;
+            emit!(BarterRingStarted { id: ring.id });
+        }
+
+        Ok(())
+    }
+
+    pub fn submit_ring_delivery(ctx: Context<SubmitRingDelivery>, delivery_hash: String) -> Result<()> {
+        let ring = &mut ctx.accounts.ring;
+        let caller = ctx.accounts.caller.key();
+
+        require!(ring.status == BarterStatus::InProgress, ErrorCode::BarterNotInProgress);
+        require!(Clock::get()?.unix_timestamp <= ring.deliver_by, ErrorCode::DeliveryDeadlinePassed);
+        require!(delivery_hash.len() <= MAX_DELIVERY_HASH_LEN, ErrorCode::DeliveryHashTooLong);
+        let index = ring.position_of(&caller).ok_or(ErrorCode::NotBarterParticipant)?;
+        // Once received, a delivery can no longer be swapped out from under the confirmation
+        require!(!ring.participants[index].confirmed, ErrorCode::DeliveryAlreadyConfirmed);
+
+        ring.participants[index].delivery_hash = Some(delivery_hash.clone());
+
+        emit!(RingDeliverySubmitted {
+            ring_id: ring.id,
+            participant: caller,
+            delivery_hash,
+        });
+
+        Ok(())
+    }
+
+    /// Confirms the delivery of the previous participant in the ring, which is addressed to the caller
+    pub fn confirm_ring_link(ctx: Context<ConfirmRingLink>) -> Result<()> {
+        let ring = &mut ctx.accounts.ring;
+        let caller = ctx.accounts.caller.key();
+
+        require!(ring.status == BarterStatus::InProgress, ErrorCode::BarterNotInProgress);
+        let index = ring.position_of(&caller).ok_or(ErrorCode::NotBarterParticipant)?;
+        let giver_index = ring.giver_to(index);
+        let giver = &mut ring.participants[giver_index];
+        require!(giver.delivery_hash.is_some(), ErrorCode::DeliveryNotReady);
+
+        giver.confirmed = true;
+        let giver_wallet = giver.wallet;
+
+        emit!(RingLinkConfirmed {
+            ring_id: ring.id,
+            giver: giver_wallet,
+            confirmed_by: caller,
+        });
+
+        if ring.participants.iter().all(|participant| participant.confirmed) {
+            ring.status = BarterStatus::Completed;
+            emit!(BarterRingCompleted { id: ring.id });
+        }
+
+        Ok(())
+    }
+
+    pub fn cancel_barter_ring(ctx: Context<CancelBarterRing>) -> Result<()> {
+        let ring = &mut ctx.accounts.ring;
+        require!(ring.status == BarterStatus::Open, ErrorCode::BarterNotOpen);
+        require!(ctx.accounts.initiator.key() == ring.initiator, ErrorCode::BarterNotInitiator);
+
+        ring.status = BarterStatus::Cancelled;
+
+        emit!(BarterRingCancelled { id: ring.id });
+
+        Ok(())
+    }
+
+    /// Ends a ring that missed its deadline, whether or not everyone joined. Rings hold no
+    /// stakes, so nothing moves; the initiator can then close it.
+    pub fn expire_barter_ring(ctx: Context<ExpireBarterRing>) -> Result<()> {
+        let ring = &mut ctx.accounts.ring;
+        let now = Clock::get()?.unix_timestamp;
+
+        require!(
+            matches!(ring.status, BarterStatus::Open | BarterStatus::InProgress) && now > ring.deliver_by,
+            ErrorCode::BarterNotExpired
+        );
+
+        let previous_status = ring.status.clone();
+        ring.status = BarterStatus::Expired;
+
+        emit!(BarterRingExpired {
+            id: ring.id,
+            previous_status,
+        });
+
+        Ok(())
+    }
+
+    pub fn close_barter_ring(ctx: Context<CloseBarterRing>) -> Result<()> {
+        require!(
+            matches!(
+                ctx.accounts.ring.status,
+                BarterStatus::Completed | BarterStatus::Cancelled | BarterStatus::Expired
+            ),
+            ErrorCode::NotClosable
+        );
+
+        Ok(())
+    }
+
+    // ── Arbitration ──
+
+    pub fn init_arbitrator_pool(
+        ctx: Context<InitArbitratorPool>,
+        min_stake_lamports: u64,
+        jurors_per_dispute: u8,
+        slash_bps: u16,
+        commit_secs: i64,
+        reveal_secs: i64,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+
+        require!(ctx.accounts.authority.key() == ctx.accounts.global.authority, ErrorCode::NotAuthority);
+        // An odd jury can't tie when everyone reveals
+        require!(
+            jurors_per_dispute as usize <= MAX_JURORS && jurors_per_dispute % 2 == 1,
+            ErrorCode::InvalidPoolConfig
+        );
+        require!(slash_bps as u64 <= BPS_DENOMINATOR, ErrorCode::InvalidPoolConfig);
+        require!(commit_secs > 0 && reveal_secs > 0, ErrorCode::InvalidPoolConfig);
+
+        pool.global = ctx.accounts.global.key();
+        pool.min_stake_lamports = min_stake_lamports;
+        pool.jurors_per_dispute = jurors_per_dispute;
+        pool.slash_bps = slash_bps;
+        pool.commit_secs = commit_secs;
+        pool.reveal_secs = reveal_secs;
+        pool.members = Vec::new();
+        pool.bump = ctx.bumps.pool;
+
+        emit!(ArbitratorPoolInitialized {
+            min_stake_lamports,
+            jurors_per_dispute,
+            slash_bps,
+            commit_secs,
+            reveal_secs,
+        });
+
+        Ok(())
+    }
+
+    pub fn join_arbitrator_pool(ctx: Context<JoinArbitratorPool>, stake_lamports: u64) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let wallet = ctx.accounts.wallet.key();
+
+        require!(stake_lamports >= pool.min_stake_lamports, ErrorCode::StakeTooLow);
+        require!(pool.members.len() < MAX_POOL_MEMBERS, ErrorCode::PoolFull);
+
+        let transfer_ix = anchor_lang::system_program::Transfer {
+            from: ctx.accounts.wallet.to_account_info(),
+            to: ctx.accounts.arbitrator.to_account_info(),
+        };
+        anchor_lang::system_program::transfer(
+            CpiContext::new(ctx.accounts.system_program.to_account_info(), transfer_ix),
+            stake_lamports,
+        )?;
+
+        let arbitrator = &mut ctx.accounts.arbitrator;
+        arbitrator.global = ctx.accounts.global.key();
+        arbitrator.wallet = wallet;
+        arbitrator.stake_lamports = stake_lamports;
+        arbitrator.active_cases = 0;
+        arbitrator.slashed_lamports = 0;
+        arbitrator.unbonding_until = None;
+        arbitrator.bump = ctx.bumps.arbitrator;
+
+        pool.members.push(wallet);
+
+        emit!(ArbitratorJoined {
+            wallet,
+            stake_lamports,
+        });
+
+        Ok(())
+    }
+
+    /// Leaves the pool straight away; the stake unlocks after twice a full voting window,
+    /// and only once every jury the arbitrator was drawn for has been finalized.
+    pub fn leave_arbitrator_pool(ctx: Context<LeaveArbitratorPool>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let arbitrator = &mut ctx.accounts.arbitrator;
+
+        require!(arbitrator.unbonding_until.is_none(), ErrorCode::AlreadyUnbonding);
+
+        pool.members.retain(|member| *member != arbitrator.wallet);
+        let unbonding_until = Clock::get()?.unix_timestamp + 2 * (pool.commit_secs + pool.reveal_secs);
+        arbitrator.unbonding_until = Some(unbonding_until);
+
+        emit!(ArbitratorLeft {
+            wallet: arbitrator.wallet,
+            unbonding_until,
+        });
+
+        Ok(())
+    }
+
+    pub fn withdraw_arbitrator_stake(ctx: Context<WithdrawArbitratorStake>) -> Result<()> {
+        let arbitrator = &ctx.accounts.arbitrator;
+        let now = Clock::get()?.unix_timestamp;
+
+        require!(
+            arbitrator.unbonding_until.is_some_and(|unbonding_until| now >= unbonding_until),
+            ErrorCode::StakeLocked
+        );
+        require!(arbitrator.active_cases == 0, ErrorCode::StakeLocked);
+
+        emit!(ArbitratorWithdrawn {
+            wallet: arbitrator.wallet,
+            stake_lamports: arbitrator.stake_lamports,
+        });
+
+        Ok(())
+    }
+
+    /// Draws the jury for a disputed deal from the pool once the evidence window has
+    /// closed, seeded by the most recent slot hash and the deal's address. The deal's own
+    /// parties are never drawn. The jurors aren't known until the draw lands, so their
+    /// stakes are locked, and voting opened, by a separate `lock_jury` within one voting
+    /// window of the draw. A jury left unlocked past then no longer binds the dispute.
+    pub fn draw_jury(ctx: Context<DrawJury>) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let deal = &mut ctx.accounts.deal;
+        let now = Clock::get()?.unix_timestamp;
+
+        require!(deal.status == DealStatus::Disputed, ErrorCode::DealNotDisputed);
+        require!(!deal.arbitrated, ErrorCode::JuryAlreadyDrawn);
+        require!(
+            deal.evidence_deadline.is_none_or(|deadline| now > deadline),
+            ErrorCode::EvidenceWindowOpen
+        );
+
+        let slot_hashes_data = ctx.accounts.slot_hashes.try_borrow_data()?;
+        // Layout: u64 entry count, then (u64 slot, [u8; 32] hash) entries, newest first
+        let recent_hash = slot_hashes_data.get(16..48).ok_or(ErrorCode::NotEnoughArbitrators)?;
+        let seed = hashv(&[recent_hash, deal.key().as_ref()]).to_bytes();
+        let jurors = select_jurors(
+            &pool.members,
+            &[deal.client, deal.provider],
+            pool.jurors_per_dispute as usize,
+            &seed,
+        )?;
+
+        let jury = &mut ctx.accounts.jury;
+        jury.global = deal.global;
+        jury.deal_id = deal.id;
+        jury.milestone = deal.current_milestone;
+        jury.jurors = jurors
+            .iter()
+            .map(|wallet| JurorVote {
+                wallet: *wallet,
+                commitment: None,
+                vote: None,
+            })
+            .collect();
+        // Unbonding outlasts this window, so every drawn juror's stake is still there to lock
+        jury.lock_deadline = now + pool.commit_secs + pool.reveal_secs;
+        jury.commit_deadline = 0;
+        jury.reveal_deadline = 0;
+        jury.verdict = None;
+        jury.locked = false;
+        jury.finalized = false;
+        jury.bump = ctx.bumps.jury;
+
+        deal.arbitrated = true;
+
+        emit!(JuryDrawn {
+            deal_id: deal.id,
+            jurors,
+            lock_deadline: ctx.accounts.jury.lock_deadline,
+        });
+
+        Ok(())
+    }
+
+    /// Locks the drawn jurors' stakes until the jury is finalized and opens the commit
+    /// window. Anyone can call it, up to the jury's `lock_deadline`. The jurors' `Arbitrator`
+    /// accounts are passed as writable remaining accounts, in jury order.
+    pub fn lock_jury<'info>(ctx: Context<'_, '_, 'info, 'info, LockJury<'info>>) -> Result<()> {
+        let jury = &mut ctx.accounts.jury;
+        let pool = &ctx.accounts.pool;
+        let now = Clock::get()?.unix_timestamp;
+
+        require!(!jury.locked, ErrorCode::JuryAlreadyLocked);
+        require!(now <= jury.lock_deadline, ErrorCode::JuryLockExpired);
+        require!(
+            ctx.remaining_accounts.len() == jury.jurors.len(),
+            ErrorCode::InvalidArbitratorAccount
+        );
+
+        let global_key = ctx.accounts.global.key();
+        for (vote, info) in jury.jurors.iter().zip(ctx.remaining_accounts) {
+            let mut arbitrator = load_arbitrator(info, &global_key, &vote.wallet)?;
+            arbitrator.active_cases += 1;
+            arbitrator.exit(&crate::ID)?;
+        }
+
+        jury.commit_deadline = now + pool.commit_secs;
+        jury.reveal_deadline = jury.commit_deadline + pool.reveal_secs;
+        jury.locked = true;
+
+        emit!(JuryLocked {
+            deal_id: jury.deal_id,
+            commit_deadline: jury.commit_deadline,
+            reveal_deadline: jury.reveal_deadline,
+        });
+
+        Ok(())
+    }
+
+    /// `commitment` is `hashv([borsh(resolution), salt, juror])`, revealed later
+    pub fn commit_vote(ctx: Context<CommitVote>, commitment: [u8; 32]) -> Result<()> {
+        let jury = &mut ctx.accounts.jury;
+        let juror = ctx.accounts.juror.key();
+
+        require!(jury.locked, ErrorCode::JuryNotLocked);
+        require!(Clock::get()?.unix_timestamp <= jury.commit_deadline, ErrorCode::CommitWindowClosed);
+        let vote = jury
+            .jurors
+            .iter_mut()
+            .find(|vote| vote.wallet == juror)
+            .ok_or(ErrorCode::NotJuror)?;
+        vote.commitment = Some(commitment);
+
+        emit!(VoteCommitted {
+            deal_id: jury.deal_id,
+            juror,
+        });
+
+        Ok(())
+    }
+
+    pub fn reveal_vote(ctx: Context<RevealVote>, resolution: DisputeResolution, salt: [u8; 32]) -> Result<()> {
+        let jury = &mut ctx.accounts.jury;
+        let juror = ctx.accounts.juror.key();
+        let now = Clock::get()?.unix_timestamp;
+
+        require!(now > jury.commit_deadline, ErrorCode::CommitWindowOpen);
+        require!(now <= jury.reveal_deadline, ErrorCode::RevealWindowClosed);
+        let vote = jury
+            .jurors
+            .iter_mut()
+            .find(|vote| vote.wallet == juror)
+            .ok_or(ErrorCode::NotJuror)?;
+
+        let mut encoded = Vec::new();
+        resolution.serialize(&mut encoded)?;
+        let expected = hashv(&[&encoded, &salt, juror.as_ref()]).to_bytes();
+        require!(vote.commitment == Some(expected), ErrorCode::CommitmentMismatch);
+        // An unexecutable verdict would leave the escrow stuck, since the authority can't override it
+        resolution.validate()?;
+
+        vote.vote = Some(resolution.clone());
+
+        emit!(VoteRevealed {
+            deal_id: jury.deal_id,
+            juror,
+            resolution,
+        });
+
+        Ok(())
+    }
+
+    /// Tallies revealed votes once the reveal window closes (or everyone has revealed).
+    /// A resolution backed by a majority of the whole jury becomes the verdict for
+    /// `resolve_dispute`; jurors who voted otherwise or never revealed are slashed to the
+    /// treasury. Without one the dispute goes back to the authority; the milestone's jury
+    /// account already exists, so a failed jury is final and can't be redrawn. Jurors'
+    /// `Arbitrator` accounts are passed as writable remaining accounts, in jury order.
+    pub fn finalize_jury<'info>(ctx: Context<'_, '_, 'info, 'info, FinalizeJury<'info>>) -> Result<()> {
+        let jury = &mut ctx.accounts.jury;
+        let pool = &mut ctx.accounts.pool;
+        let now = Clock::get()?.unix_timestamp;
+
+        require!(jury.locked, ErrorCode::JuryNotLocked);
+        require!(!jury.finalized, ErrorCode::JuryFinalized);
+        require!(
+            now > jury.reveal_deadline || jury.jurors.iter().all(|vote| vote.vote.is_some()),
+            ErrorCode::RevealWindowOpen
+        );
+        require!(
+            ctx.remaining_accounts.len() == jury.jurors.len(),
+            ErrorCode::InvalidArbitratorAccount
+        );
+
+        let verdict = majority_vote(&jury.jurors);
+        let global_key = ctx.accounts.global.key();
+        let mut slashed_total = 0;
+        for (vote, info) in jury.jurors.iter().zip(ctx.remaining_accounts) {
+            // Stakes are locked from `lock_jury`, so every juror's account is still open
+            let mut arbitrator = load_arbitrator(info, &global_key, &vote.wallet)?;
+            arbitrator.active_cases = arbitrator.active_cases.saturating_sub(1);
+            let sided_with_verdict = verdict.is_some() && vote.vote == verdict;
+            let revealed_without_verdict = verdict.is_none() && vote.vote.is_some();
+            if !sided_with_verdict && !revealed_without_verdict {
+                let slashed = (arbitrator.stake_lamports as u128 * pool.slash_bps as u128 / BPS_DENOMINATOR as u128) as u64;
+                **info.try_borrow_mut_lamports()? -= slashed;
+                **ctx.accounts.treasury.try_borrow_mut_lamports()? += slashed;
+                arbitrator.stake_lamports -= slashed;
+                arbitrator.slashed_lamports += slashed;
+                slashed_total += slashed;
+                if arbitrator.stake_lamports < pool.min_stake_lamports {
+                    pool.members.retain(|member| *member != arbitrator.wallet);
+                }
+            }
+            arbitrator.exit(&crate::ID)?;
+        }
+
+        jury.verdict = verdict.clone();
+        jury.finalized = true;
+        if verdict.is_none() {
+            ctx.accounts.deal.arbitrated = false;
+        }
+
+        emit!(JuryFinalized {
+            deal_id: jury.deal_id,
+            verdict,
+            slashed_lamports: slashed_total,
+        });
+
+        Ok(())
+    }
+
+    // ── Reputation ──
+
+    pub fn rate_deal(ctx: Context<RateDeal>, rating: u8) -> Result<()> {
+        let deal = &mut ctx.accounts.deal;
+        let rater = ctx.accounts.rater.key();
+
+        require!(
+            deal.status == DealStatus::Completed
+                || deal.status == DealStatus::Cancelled
+                || deal.status == DealStatus::PartiallyCompleted,
+            ErrorCode::DealNotFinished
+        );
+        require!((1..=5).contains(&rating), ErrorCode::InvalidRating);
+
+        if rater == deal.client {
+            require!(!deal.client_rated, ErrorCode::AlreadyRated);
+            deal.client_rated = true;
+        } else {
+            require!(!deal.provider_rated, ErrorCode::AlreadyRated);
+            deal.provider_rated = true;
+        }
+
+        let ratee = ctx.accounts.ratee.key();
+        let ratee_reputation = &mut ctx.accounts.ratee_reputation;
+        ratee_reputation.ensure_initialized(ctx.accounts.global.key(), ratee, ctx.bumps.ratee_reputation);
+        ratee_reputation.rating_sum += rating as u64;
+        ratee_reputation.rating_count += 1;
+
+        emit!(DealRated {
+            deal_id: deal.id,
+            rater,
+            ratee,
+            rating,
+        });
+
+        Ok(())
+    }
+
+    // ── Account Closing ──
+
+    pub fn close_need(ctx: Context<CloseNeed>, archive: bool) -> Result<()> {
+        let need = &ctx.accounts.need;
+        require!(
+            need.status == NeedStatus::Completed
+                || need.status == NeedStatus::Cancelled
+                || need.status == NeedStatus::PartiallyCompleted,
+            ErrorCode::NotClosable
+        );
+
+        if archive {
+            emit!(NeedArchived {
+                id: need.id,
+                creator: need.creator,
+                category_id: need.category_id,
+                budget_lamports: need.budget_lamports,
+                mint: need.mint,
+                status: need.status.clone(),
+                created_at: need.created_at,
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn close_offer(ctx: Context<CloseOffer>, archive: bool) -> Result<()> {
+        let offer = &ctx.accounts.offer;
+        require!(offer.status != OfferStatus::Pending, ErrorCode::NotClosable);
+
+        if archive {
+            emit!(OfferArchived {
+                id: offer.id,
+                need_id: offer.need_id,
+                provider: offer.provider,
+                price_lamports: offer.price_lamports,
+                status: offer.status.clone(),
+                created_at: offer.created_at,
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn close_deal(ctx: Context<CloseDeal>, archive: bool) -> Result<()> {
+        let deal = &ctx.accounts.deal;
+        require!(
+            deal.status == DealStatus::Completed
+                || deal.status == DealStatus::Cancelled
+                || deal.status == DealStatus::PartiallyCompleted,
+            ErrorCode::NotClosable
+        );
+
+        let escrow = EscrowTokens::load(
+            deal.mint,
+            &ctx.accounts.mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.token_program,
+        )?;
+        if let Some(escrow) = escrow {
+            close_vault(deal, &escrow, &ctx.accounts.client)?;
+        }
+
+        if archive {
+            emit!(DealArchived {
+                id: deal.id,
+                need_id: deal.need_id,
+                offer_id: deal.offer_id,
+                client: deal.client,
+                provider: deal.provider,
+                amount_lamports: deal.amount_lamports,
+                released_lamports: deal.released_lamports,
+                mint: deal.mint,
+                status: deal.status.clone(),
+                delivery_hash: deal.delivery_hash.clone(),
+                created_at: deal.created_at,
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn close_barter(ctx: Context<CloseBarter>, archive: bool) -> Result<()> {
+        let barter = &ctx.accounts.barter;
+        require!(
+            matches!(
+                barter.status,
+                BarterStatus::Completed | BarterStatus::Cancelled | BarterStatus::Expired
+            ),
+            ErrorCode::NotClosable
+        );
+
+        if archive {
+            emit!(BarterArchived {
+                id: barter.id,
+                initiator: barter.initiator,
+                counterpart: barter.counterpart,
+                status: barter.status.clone(),
+                side_a_hash: barter.side_a_hash.clone(),
+                side_b_hash: barter.side_b_hash.clone(),
+                created_at: barter.created_at,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Returns an evidence account's rent to whoever opened it, once its dispute
+    /// is resolved or the deal itself has been closed.
+    pub fn close_dispute_evidence(ctx: Context<CloseDisputeEvidence>) -> Result<()> {
+        let deal_data = ctx.accounts.deal.try_borrow_data()?;
+        if !deal_data.is_empty() {
+            let deal = Deal::try_deserialize(&mut &deal_data[..])?;
+            require!(
+                deal.status != DealStatus::Disputed || deal.current_milestone != ctx.accounts.evidence.milestone,
+                ErrorCode::NotClosable
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Marks still-pending offers on `need_id` as rejected once another offer has won.
+/// Offers cancelled, or cancelled and closed, since the client built the transaction
+/// are skipped, so a competing provider can't make the acceptance fail.
+pub fn reject_competing_offers<'info>(
+    offer_accounts: &'info [AccountInfo<'info>],
+    global: Pubkey,
+    need_id: u64,
+    accepted_offer_id: u64,
+) -> Result<()> {
+    for offer_info in offer_accounts {
+        require!(offer_info.is_writable, ErrorCode::InvalidCompetingOffer);
+        if offer_info.data_is_empty() {
+            continue;
+        }
+        let mut offer = Account::<Offer>::try_from(offer_info)?;
+        require!(
+            offer.global == global && offer.need_id == need_id && offer.id != accepted_offer_id,
+            ErrorCode::InvalidCompetingOffer
+        );
+        if offer.status != OfferStatus::Pending {
+            continue;
+        }
+
+        offer.status = OfferStatus::Rejected;
+        offer.exit(&crate::ID)?;
+
+        emit!(OfferRejected {
+            id: offer.id,
+            need_id: offer.need_id,
+            provider: offer.provider,
+        });
+    }
+
+    Ok(())
+}
+
+/// Picks `count` distinct jurors from `members`, skipping `excluded`, by hashing `seed`
+/// with an increasing nonce.
+pub fn select_jurors(members: &[Pubkey], excluded: &[Pubkey], count: usize, seed: &[u8; 32]) -> Result<Vec<Pubkey>> {
+    let eligible: Vec<Pubkey> = members
+        .iter()
+        .filter(|member| !excluded.contains(member))
+        .copied()
+        .collect();
+    require!(count > 0 && eligible.len() >= count, ErrorCode::NotEnoughArbitrators);
+
+    let mut jurors = Vec::with_capacity(count);
+    let mut nonce: u64 = 0;
+    while jurors.len() < count {
+        let draw = hashv(&[seed, &nonce.to_le_bytes()]).to_bytes();
+        let index = u64::from_le_bytes(draw[..8].try_into().unwrap()) % eligible.len() as u64;
+        let juror = eligible[index as usize];
+        if !jurors.contains(&juror) {
+            jurors.push(juror);
+        }
+        nonce += 1;
+    }
+
+    Ok(jurors)
+}
+
+/// Loads a juror's `Arbitrator` account passed as a remaining account, checking it is
+/// the wallet's PDA and writable.
+pub fn load_arbitrator<'info>(
+    info: &'info AccountInfo<'info>,
+    global: &Pubkey,
+    wallet: &Pubkey,
+) -> Result<Account<'info, Arbitrator>> {
+    let (expected, _) = Pubkey::find_program_address(&[b"arbitrator", global.as_ref(), wallet.as_ref()], &crate::ID);
+    require_keys_eq!(info.key(), expected, ErrorCode::InvalidArbitratorAccount);
+    require!(info.is_writable, ErrorCode::InvalidArbitratorAccount);
+    Account::try_from(info)
+}
+
+/// The resolution revealed by more than half of the whole jury, if any. Counting jurors
+/// who never revealed keeps a lone revealer from deciding the dispute.
+pub fn majority_vote(jurors: &[JurorVote]) -> Option<DisputeResolution> {
+    let revealed: Vec<&DisputeResolution> = jurors.iter().filter_map(|vote| vote.vote.as_ref()).collect();
+    revealed
+        .iter()
+        .find(|candidate| revealed.iter().filter(|vote| vote == candidate).count() * 2 > jurors.len())
+        .map(|verdict| (*verdict).clone())
+}
+
+/// Lowercase ASCII letters, digits and dashes, so "Audit" and "audit" can't both exist
+pub fn is_valid_category_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|byte| byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'-')
+}
+
+/// Milestones are optional, but when given they must be non-zero and add up to the price.
+pub fn validate_milestones(milestones: &[u64], price: u64) -> Result<()> {
+    if milestones.is_empty() {
+        return Ok(());
+    }
+    require!(milestones.len() <= MAX_MILESTONES, ErrorCode::InvalidMilestones);
+    require!(milestones.iter().all(|amount| *amount > 0), ErrorCode::InvalidMilestones);
+
+    let total = milestones
+        .iter()
+        .try_fold(0u64, |total, amount| total.checked_add(*amount))
+        .ok_or(ErrorCode::InvalidMilestones)?;
+    require!(total == price, ErrorCode::InvalidMilestones);
+
+    Ok(())
+}
+
+// Escrow helpers
+
+/// Token accounts backing a mint-denominated deal. SOL deals have none.
+pub struct EscrowTokens<'a, 'info> {
+    pub mint: &'a InterfaceAccount<'info, Mint>,
+    pub vault: &'a InterfaceAccount<'info, TokenAccount>,
+    pub token_program: &'a Interface<'info, TokenInterface>,
+}
+
+impl<'a, 'info> EscrowTokens<'a, 'info> {
+    pub fn load(
+        deal_mint: Option<Pubkey>,
+        mint: &'a Option<Box<InterfaceAccount<'info, Mint>>>,
+        vault: &'a Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+        token_program: &'a Option<Interface<'info, TokenInterface>>,
+    ) -> Result<Option<Self>> {
+        let Some(deal_mint) = deal_mint else {
+            return Ok(None);
+        };
+        let (Some(mint), Some(vault), Some(token_program)) = (mint, vault, token_program) else {
+            return err!(ErrorCode::TokenAccountsMissing);
+        };
+        require_keys_eq!(mint.key(), deal_mint, ErrorCode::MintMismatch);
+
+        Ok(Some(Self { mint, vault, token_program }))
+    }
+}
+
+/// Pays `amount` out of a program-owned escrow account. SOL is moved straight off
+/// `owner`; tokens are moved out of the vault with `owner` as signer, into a token
+/// account owned by `recipient`.
+pub fn pay_from_escrow<'info>(
+    owner: &AccountInfo<'info>,
+    signer_seeds: &[&[u8]],
+    escrow: Option<&EscrowTokens<'_, 'info>>,
+    recipient: &AccountInfo<'info>,
+    recipient_token_account: Option<&InterfaceAccount<'info, TokenAccount>>,
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+
+    let Some(escrow) = escrow else {
+        **owner.lamports.borrow_mut() -= amount;
+        **recipient.lamports.borrow_mut() += amount;
+        return Ok(());
+    };
+
+    let recipient_token_account = recipient_token_account.ok_or(ErrorCode::TokenAccountsMissing)?;
+    require_keys_eq!(recipient_token_account.owner, recipient.key(), ErrorCode::InvalidTokenAccount);
+    require_keys_eq!(recipient_token_account.mint, escrow.mint.key(), ErrorCode::MintMismatch);
+
+    let transfer_ix = TransferChecked {
+        from: escrow.vault.to_account_info(),
+        mint: escrow.mint.to_account_info(),
+        to: recipient_token_account.to_account_info(),
+        authority: owner.clone(),
+    };
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(escrow.token_program.to_account_info(), transfer_ix, &[signer_seeds]),
+        amount,
+        escrow.mint.decimals,
+    )
+}
+
+/// Closes an emptied token vault owned by `owner`, returning its rent to `destination`.
+/// Token-2022 transfer-fee mints withhold part of each deposit in the vault, which
+/// blocks the close, so those fees are harvested to the (writable) mint first.
+pub fn close_escrow_vault<'info>(
+    owner: &AccountInfo<'info>,
+    signer_seeds: &[&[u8]],
+    escrow: &EscrowTokens<'_, 'info>,
+    destination: &AccountInfo<'info>,
+) -> Result<()> {
+    if withheld_transfer_fees(escrow.vault)? > 0 {
+        let harvest_ix = HarvestWithheldTokensToMint {
+            token_program_id: escrow.token_program.to_account_info(),
+            mint: escrow.mint.to_account_info(),
+        };
+        harvest_withheld_tokens_to_mint(
+            CpiContext::new(escrow.token_program.to_account_info(), harvest_ix),
+            vec![escrow.vault.to_account_info()],
+        )?;
+    }
+
+    let close_ix = CloseAccount {
+        account: escrow.vault.to_account_info(),
+        destination: destination.clone(),
+        authority: owner.clone(),
+    };
+    token_interface::close_account(CpiContext::new_with_signer(
+        escrow.token_program.to_account_info(),
+        close_ix,
+        &[signer_seeds],
+    ))
+}
+
+/// Transfer fees withheld in a Token-2022 account; zero for classic SPL accounts.
+pub fn withheld_transfer_fees(account: &InterfaceAccount<'_, TokenAccount>) -> Result<u64> {
+    let info = account.to_account_info();
+    if *info.owner != spl_token_2022::ID {
+        return Ok(0);
+    }
+    let data = info.try_borrow_data()?;
+    let state = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&data)?;
+    Ok(state
+        .get_extension::<TransferFeeAmount>()
+        .map(|fees| u64::from(fees.withheld_amount))
+        .unwrap_or(0))
+}
+
+/// Pays `amount` out of a deal's escrow, with the deal PDA as signer.
+pub fn release_escrow<'info>(
+    deal: &Account<'info, Deal>,
+    escrow: Option<&EscrowTokens<'_, 'info>>,
+    recipient: &AccountInfo<'info>,
+    recipient_token_account: Option<&InterfaceAccount<'info, TokenAccount>>,
+    amount: u64,
+) -> Result<()> {
+    let deal_id = deal.id.to_le_bytes();
+    let bump = [deal.bump];
+    let signer_seeds: &[&[u8]] = &[b"deal", deal.global.as_ref(), deal_id.as_ref(), &bump];
+    pay_from_escrow(&deal.to_account_info(), signer_seeds, escrow, recipient, recipient_token_account, amount)
+}
+
+/// Closes a settled deal's token vault, returning its rent to `destination`.
+pub fn close_vault<'info>(
+    deal: &Account<'info, Deal>,
+    escrow: &EscrowTokens<'_, 'info>,
+    destination: &AccountInfo<'info>,
+) -> Result<()> {
+    let deal_id = deal.id.to_le_bytes();
+    let bump = [deal.bump];
+    let signer_seeds: &[&[u8]] = &[b"deal", deal.global.as_ref(), deal_id.as_ref(), &bump];
+    close_escrow_vault(&deal.to_account_info(), signer_seeds, escrow, destination)
+}
+
+/// Moves one side's barter stake into escrow: SOL onto the barter PDA, tokens into its vault.
+pub fn post_collateral<'info>(
+    barter: &Account<'info, Barter>,
+    escrow: Option<&EscrowTokens<'_, 'info>>,
+    staker: &Signer<'info>,
+    staker_token_account: Option<&InterfaceAccount<'info, TokenAccount>>,
+    system_program: &Program<'info, System>,
+) -> Result<()> {
+    let amount = barter.collateral_lamports;
+    if amount == 0 {
+        return Ok(());
+    }
+
+    let Some(escrow) = escrow else {
+        let transfer_ix = anchor_lang::system_program::Transfer {
+            from: staker.to_account_info(),
+            to: barter.to_account_info(),
+        };
+        return anchor_lang::system_program::transfer(
+            CpiContext::new(system_program.to_account_info(), transfer_ix),
+            amount,
+        );
+    };
+
+    let staker_token_account = staker_token_account.ok_or(ErrorCode::TokenAccountsMissing)?;
+    let transfer_ix = TransferChecked {
+        from: staker_token_account.to_account_info(),
+        mint: escrow.mint.to_account_info(),
+        to: escrow.vault.to_account_info(),
+        authority: staker.to_account_info(),
+    };
+    token_interface::transfer_checked(
+        CpiContext::new(escrow.token_program.to_account_info(), transfer_ix),
+        amount,
+        escrow.mint.decimals,
+    )
+}
+
+/// Pays out everything staked on a barter, `initiator_bps` of it to the initiator and
+/// the rest to the counterpart, then closes the token vault. The pool is whatever is
+/// actually escrowed, so transfer-fee mints are split pro rata and their withheld fees
+/// are harvested before the close. Returns both amounts.
+pub fn release_collateral<'info>(
+    barter: &Account<'info, Barter>,
+    escrow: Option<&EscrowTokens<'_, 'info>>,
+    initiator: (&AccountInfo<'info>, Option<&InterfaceAccount<'info, TokenAccount>>),
+    counterpart: (&AccountInfo<'info>, Option<&InterfaceAccount<'info, TokenAccount>>),
+    initiator_bps: u64,
+) -> Result<(u64, u64)> {
+    if barter.collateral_lamports == 0 {
+        return Ok((0, 0));
+    }
+
+    let barter_info = barter.to_account_info();
+    let pool = match escrow {
+        Some(escrow) => escrow.vault.amount,
+        None => barter_info
+            .lamports()
+            .saturating_sub(Rent::get()?.minimum_balance(barter_info.data_len())),
+    };
+    let initiator_amount = (pool as u128 * initiator_bps as u128 / BPS_DENOMINATOR as u128) as u64;
+    let counterpart_amount = pool - initiator_amount;
+
+    let barter_id = barter.id.to_le_bytes();
+    let bump = [barter.bump];
+    let signer_seeds: &[&[u8]] = &[b"barter", barter.global.as_ref(), barter_id.as_ref(), &bump];
+    pay_from_escrow(&barter_info, signer_seeds, escrow, initiator.0, initiator.1, initiator_amount)?;
+    pay_from_escrow(&barter_info, signer_seeds, escrow, counterpart.0, counterpart.1, counterpart_amount)?;
+    if let Some(escrow) = escrow {
+        close_escrow_vault(&barter_info, signer_seeds, escrow, initiator.0)?;
+    }
+
+    Ok((initiator_amount, counterpart_amount))
+}
+
+/// Pays `amount` of escrow to the provider, routing the deal's protocol fee to the
+/// treasury. Recipients are `(wallet, token account)` pairs. Returns the fee taken.
+pub fn release_to_provider<'info>(
+    deal: &Account<'info, Deal>,
+    escrow: Option<&EscrowTokens<'_, 'info>>,
+    provider: (&AccountInfo<'info>, Option<&InterfaceAccount<'info, TokenAccount>>),
+    treasury: (&AccountInfo<'info>, Option<&InterfaceAccount<'info, TokenAccount>>),
+    amount: u64,
+) -> Result<u64> {
+    let fee = deal.fee_for(amount);
+    release_escrow(deal, escrow, provider.0, provider.1, amount - fee)?;
+    release_escrow(deal, escrow, treasury.0, treasury.1, fee)?;
+    Ok(fee)
+}
+
+// Accounts structs
+#[derive(Accounts)]
+#[instruction(global_id: u64)]
+pub struct Initialize<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = Global::SIZE,
+        seeds = [b"global", global_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub global: Account<'info, Global>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeAuthority<'info> {
+    #[account(
+        mut,
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    #[account(
+        mut,
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    pub pending_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RenounceAuthority<'info> {
+    #[account(
+        mut,
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPaused<'info> {
+    #[account(
+        mut,
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetEvidenceWindow<'info> {
+    #[account(
+        mut,
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(fee_bps: u16, fee_treasury: Pubkey)]
+pub struct SetFeeConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    /// CHECK: The new fee treasury, only read for its balance
+    #[account(address = fee_treasury @ ErrorCode::InvalidTreasury)]
+    pub treasury: UncheckedAccount<'info>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(name: String)]
+pub struct CreateCategory<'info> {
+    #[account(
+        mut,
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = Category::SIZE,
+        seeds = [b"category", global.key().as_ref(), global.category_counter.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub category: Account<'info, Category>,
+
+    /// Claims the name; seeded by its hash since names may exceed the seed length limit
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = CategoryName::SIZE,
+        seeds = [b"category_name", global.key().as_ref(), hashv(&[name.as_bytes()]).as_ref()],
+        bump
+    )]
+    pub category_name: Account<'info, CategoryName>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateCategory<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        seeds = [b"category", global.key().as_ref(), category.id.to_le_bytes().as_ref()],
+        bump = category.bump
+    )]
+    pub category: Account<'info, Category>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateNeed<'info> {
+    #[account(
+        mut,
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        init,
+        payer = creator,
+        space = Need::SIZE,
+        seeds = [b"need", global.key().as_ref(), global.need_counter.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub need: Account<'info, Need>,
+
+    #[account(
+        seeds = [b"category", global.key().as_ref(), category.id.to_le_bytes().as_ref()],
+        bump = category.bump
+    )]
+    pub category: Account<'info, Category>,
+
+    /// Escrow mint for token-denominated needs; omit for SOL
+    pub mint: Option<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(need_id: u64)]
+pub struct CreateOffer<'info> {
+    #[account(
+        mut,
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        seeds = [b"need", global.key().as_ref(), need_id.to_le_bytes().as_ref()],
+        bump = need.bump
+    )]
+    pub need: Account<'info, Need>,
+
+    #[account(
+        init,
+        payer = provider,
+        space = Offer::SIZE,
+        seeds = [b"offer", global.key().as_ref(), global.offer_counter.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub offer: Account<'info, Offer>,
+
+    #[account(mut)]
+    pub provider: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptOffer<'info> {
+    #[account(
+        mut,
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        seeds = [b"need", global.key().as_ref(), need.id.to_le_bytes().as_ref()],
+        bump = need.bump
+    )]
+    pub need: Account<'info, Need>,
+
+    #[account(
+        mut,
+        seeds = [b"offer", global.key().as_ref(), offer.id.to_le_bytes().as_ref()],
+        bump = offer.bump
+    )]
+    pub offer: Account<'info, Offer>,
+
+    #[account(
+        seeds = [b"category", global.key().as_ref(), need.category_id.to_le_bytes().as_ref()],
+        bump = category.bump
+    )]
+    pub category: Account<'info, Category>,
+
+    #[account(
+        init,
+        payer = client,
+        space = Deal::SIZE,
+        seeds = [b"deal", global.key().as_ref(), global.deal_counter.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub deal: Box<Account<'info, Deal>>,
+
+    #[account(mut)]
+    pub client: Signer<'info>,
+
+    // Token escrow accounts, only for needs with a mint
+    pub mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        init,
+        payer = client,
+        seeds = [b"vault", deal.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = deal,
+        token::token_program = token_program,
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(mut)]
+    pub client_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SubmitDelivery<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        seeds = [b"deal", global.key().as_ref(), deal.id.to_le_bytes().as_ref()],
+        bump = deal.bump
+    )]
+    pub deal: Account<'info, Deal>,
+
+    pub provider: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ConfirmDelivery<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        seeds = [b"deal", global.key().as_ref(), deal.id.to_le_bytes().as_ref()],
+        bump = deal.bump
+    )]
+    pub deal: Account<'info, Deal>,
+
+    #[account(
+        mut,
+        constraint = need.id == deal.need_id @ ErrorCode::DealNeedMismatch,
+        seeds = [b"need", global.key().as_ref(), need.id.to_le_bytes().as_ref()],
+        bump = need.bump
+    )]
+    pub need: Account<'info, Need>,
+
+    #[account(mut)]
+    pub client: Signer<'info>,
+    
+    /// CHECK: Provider account to receive payment, must match the deal
+    #[account(mut, address = deal.provider @ ErrorCode::InvalidRecipient)]
+    pub provider: UncheckedAccount<'info>,
+
+    // Token escrow accounts, only for deals with a mint
+    pub mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", deal.key().as_ref()],
+        bump
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(mut)]
+    pub provider_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// CHECK: Fee treasury, must match global
+    #[account(mut, address = global.fee_treasury @ ErrorCode::InvalidTreasury)]
+    pub treasury: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub treasury_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+
+    #[account(
+        init_if_needed,
+        payer = client,
+        space = Reputation::SIZE,
+        seeds = [b"reputation", global.key().as_ref(), deal.client.as_ref()],
+        bump
+    )]
+    pub client_reputation: Box<Account<'info, Reputation>>,
+
+    #[account(
+        init_if_needed,
+        payer = client,
+        space = Reputation::SIZE,
+        seeds = [b"reputation", global.key().as_ref(), deal.provider.as_ref()],
+        bump
+    )]
+    pub provider_reputation: Box<Account<'info, Reputation>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RaiseDispute<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        seeds = [b"deal", global.key().as_ref(), deal.id.to_le_bytes().as_ref()],
+        bump = deal.bump
+    )]
+    pub deal: Account<'info, Deal>,
+
+    #[account(
+        init_if_needed,
+        payer = caller,
+        space = Reputation::SIZE,
+        seeds = [b"reputation", global.key().as_ref(), caller.key().as_ref()],
+        bump
+    )]
+    pub caller_reputation: Box<Account<'info, Reputation>>,
+
+    #[account(mut)]
+    pub caller: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveDispute<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        seeds = [b"deal", global.key().as_ref(), deal.id.to_le_bytes().as_ref()],
+        bump = deal.bump
+    )]
+    pub deal: Account<'info, Deal>,
+
+    #[account(
+        mut,
+        constraint = need.id == deal.need_id @ ErrorCode::DealNeedMismatch,
+        seeds = [b"need", global.key().as_ref(), need.id.to_le_bytes().as_ref()],
+        bump = need.bump
+    )]
+    pub need: Account<'info, Need>,
+
+    /// Global authority, or anyone once a jury has reached a verdict
+    #[account(mut)]
+    pub resolver: Signer<'info>,
+
+    /// The jury for the milestone under dispute
+    #[account(
+        seeds = [b"jury", deal.key().as_ref(), &[deal.current_milestone]],
+        bump = jury.bump
+    )]
+    pub jury: Option<Box<Account<'info, Jury>>>,
+
+    /// CHECK: Client account to receive refund, must match the deal
+    #[account(mut, address = deal.client @ ErrorCode::InvalidRecipient)]
+    pub client: UncheckedAccount<'info>,
+
+    /// CHECK: Provider account to receive payment, must match the deal
+    #[account(mut, address = deal.provider @ ErrorCode::InvalidRecipient)]
+    pub provider: UncheckedAccount<'info>,
+
+    // Token escrow accounts, only for deals with a mint
+    pub mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", deal.key().as_ref()],
+        bump
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(mut)]
+    pub client_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(mut)]
+    pub provider_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// CHECK: Fee treasury, must match global
+    #[account(mut, address = global.fee_treasury @ ErrorCode::InvalidTreasury)]
+    pub treasury: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub treasury_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+
+    #[account(
+        init_if_needed,
+        payer = resolver,
+        space = Reputation::SIZE,
+        seeds = [b"reputation", global.key().as_ref(), deal.client.as_ref()],
+        bump
+    )]
+    pub client_reputation: Box<Account<'info, Reputation>>,
+
+    #[account(
+        init_if_needed,
+        payer = resolver,
+        space = Reputation::SIZE,
+        seeds = [b"reputation", global.key().as_ref(), deal.provider.as_ref()],
+        bump
+    )]
+    pub provider_reputation: Box<Account<'info, Reputation>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SubmitEvidence<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        seeds = [b"deal", global.key().as_ref(), deal.id.to_le_bytes().as_ref()],
+        bump = deal.bump
+    )]
+    pub deal: Box<Account<'info, Deal>>,
+
+    /// One per disputed milestone, opened by the first submission
+    #[account(
+        init_if_needed,
+        payer = caller,
+        space = DisputeEvidence::SIZE,
+        seeds = [b"evidence", deal.key().as_ref(), &[deal.current_milestone]],
+        bump
+    )]
+    pub evidence: Box<Account<'info, DisputeEvidence>>,
+
+    #[account(mut)]
+    pub caller: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelNeed<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        seeds = [b"need", global.key().as_ref(), need.id.to_le_bytes().as_ref()],
+        bump = need.bump
+    )]
+    pub need: Account<'info, Need>,
+
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelOffer<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        seeds = [b"offer", global.key().as_ref(), offer.id.to_le_bytes().as_ref()],
+        bump = offer.bump
+    )]
+    pub offer: Account<'info, Offer>,
+
+    pub provider: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateNeed<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        seeds = [b"need", global.key().as_ref(), need.id.to_le_bytes().as_ref()],
+        bump = need.bump
+    )]
+    pub need: Account<'info, Need>,
+
+    #[account(
+        seeds = [b"category", global.key().as_ref(), need.category_id.to_le_bytes().as_ref()],
+        bump = category.bump
+    )]
+    pub category: Account<'info, Category>,
+
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateOffer<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        seeds = [b"need", global.key().as_ref(), offer.need_id.to_le_bytes().as_ref()],
+        bump = need.bump
+    )]
+    pub need: Account<'info, Need>,
+
+    #[account(
+        mut,
+        seeds = [b"offer", global.key().as_ref(), offer.id.to_le_bytes().as_ref()],
+        bump = offer.bump
+    )]
+    pub offer: Account<'info, Offer>,
+
+    pub provider: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CounterOffer<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        seeds = [b"need", global.key().as_ref(), offer.need_id.to_le_bytes().as_ref()],
+        bump = need.bump
+    )]
+    pub need: Account<'info, Need>,
+
+    #[account(
+        mut,
+        seeds = [b"offer", global.key().as_ref(), offer.id.to_le_bytes().as_ref()],
+        bump = offer.bump
+    )]
+    pub offer: Account<'info, Offer>,
+
+    pub caller: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptCounterOffer<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        seeds = [b"offer", global.key().as_ref(), offer.id.to_le_bytes().as_ref()],
+        bump = offer.bump
+    )]
+    pub offer: Account<'info, Offer>,
+
+    pub provider: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RejectOffer<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        seeds = [b"need", global.key().as_ref(), need.id.to_le_bytes().as_ref()],
+        bump = need.bump
+    )]
+    pub need: Account<'info, Need>,
+
+    #[account(
+        mut,
+        seeds = [b"offer", global.key().as_ref(), offer.id.to_le_bytes().as_ref()],
+        bump = offer.bump
+    )]
+    pub offer: Account<'info, Offer>,
+
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateBarter<'info> {
+    #[account(
+        mut,
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        init,
+        payer = initiator,
+        space = Barter::SIZE,
+        seeds = [b"barter", global.key().as_ref(), global.barter_counter.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub barter: Account<'info, Barter>,
+
+    #[account(mut)]
+    pub initiator: Signer<'info>,
+
+    // Collateral escrow accounts, only for barters staked in a mint
+    pub mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        init,
+        payer = initiator,
+        seeds = [b"vault", barter.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = barter,
+        token::token_program = token_program,
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(mut)]
+    pub initiator_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptBarter<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        seeds = [b"barter", global.key().as_ref(), barter.id.to_le_bytes().as_ref()],
+        bump = barter.bump
+    )]
+    pub barter: Account<'info, Barter>,
+
+    #[account(mut)]
+    pub caller: Signer<'info>,
+
+    // Collateral escrow accounts, only for barters staked in a mint
+    pub mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", barter.key().as_ref()],
+        bump
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(mut)]
+    pub caller_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CounterBarter<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        seeds = [b"barter", global.key().as_ref(), barter.id.to_le_bytes().as_ref()],
+        bump = barter.bump
+    )]
+    pub barter: Account<'info, Barter>,
+
+    pub caller: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptCounterBarter<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        seeds = [b"barter", global.key().as_ref(), barter.id.to_le_bytes().as_ref()],
+        bump = barter.bump
+    )]
+    pub barter: Account<'info, Barter>,
+
+    pub initiator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SubmitBarterDelivery<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        seeds = [b"barter", global.key().as_ref(), barter.id.to_le_bytes().as_ref()],
+        bump = barter.bump
+    )]
+    pub barter: Account<'info, Barter>,
+
+    pub caller: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ConfirmBarterSide<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        seeds = [b"barter", global.key().as_ref(), barter.id.to_le_bytes().as_ref()],
+        bump = barter.bump
+    )]
+    pub barter: Account<'info, Barter>,
+
+    #[account(
+        init_if_needed,
+        payer = caller,
+        space = Reputation::SIZE,
+        seeds = [b"reputation", global.key().as_ref(), barter.initiator.as_ref()],
+        bump
+    )]
+    pub initiator_reputation: Box<Account<'info, Reputation>>,
+
+    #[account(
+        init_if_needed,
+        payer = caller,
+        space = Reputation::SIZE,
+        seeds = [b"reputation", global.key().as_ref(), barter.counterpart.as_ref()],
+        bump
+    )]
+    pub counterpart_reputation: Box<Account<'info, Reputation>>,
+
+    /// CHECK: Receives the initiator's share of the collateral, must match the barter
+    #[account(mut, address = barter.initiator @ ErrorCode::InvalidRecipient)]
+    pub initiator: UncheckedAccount<'info>,
+
+    /// CHECK: Receives the counterpart's share of the collateral, must match the barter
+    #[account(mut, address = barter.counterpart @ ErrorCode::InvalidRecipient)]
+    pub counterpart: UncheckedAccount<'info>,
+
+    // Collateral escrow accounts, only for barters staked in a mint
+    #[account(mut)]
+    pub mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", barter.key().as_ref()],
+        bump
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(mut)]
+    pub initiator_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(mut)]
+    pub counterpart_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+
+    #[account(mut)]
+    pub caller: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelBarter<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        seeds = [b"barter", global.key().as_ref(), barter.id.to_le_bytes().as_ref()],
+        bump = barter.bump
+    )]
+    pub barter: Account<'info, Barter>,
+
+    #[account(mut)]
+    pub initiator: Signer<'info>,
+
+    // Collateral escrow accounts, only for barters staked in a mint
+    #[account(mut)]
+    pub mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", barter.key().as_ref()],
+        bump
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(mut)]
+    pub initiator_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+}
+
+#[derive(Accounts)]
+pub struct DisputeBarter<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        seeds = [b"barter", global.key().as_ref(), barter.id.to_le_bytes().as_ref()],
+        bump = barter.bump
+    )]
+    pub barter: Account<'info, Barter>,
+
+    #[account(
+        init_if_needed,
+        payer = caller,
+        space = Reputation::SIZE,
+        seeds = [b"reputation", global.key().as_ref(), caller.key().as_ref()],
+        bump
+    )]
+    pub caller_reputation: Box<Account<'info, Reputation>>,
+
+    #[account(mut)]
+    pub caller: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AssignBarterArbiter<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        seeds = [b"barter", global.key().as_ref(), barter.id.to_le_bytes().as_ref()],
+        bump = barter.bump
+    )]
+    pub barter: Account<'info, Barter>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveBarterDispute<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        seeds = [b"barter", global.key().as_ref(), barter.id.to_le_bytes().as_ref()],
+        bump = barter.bump
+    )]
+    pub barter: Account<'info, Barter>,
+
+    /// Global authority or the barter's assigned arbiter
+    #[account(mut)]
+    pub resolver: Signer<'info>,
+
+    /// CHECK: Receives the initiator's share of the collateral, must match the barter
+    #[account(mut, address = barter.initiator @ ErrorCode::InvalidRecipient)]
+    pub initiator: UncheckedAccount<'info>,
+
+    /// CHECK: Receives the counterpart's share of the collateral, must match the barter
+    #[account(mut, address = barter.counterpart @ ErrorCode::InvalidRecipient)]
+    pub counterpart: UncheckedAccount<'info>,
+
+    // Collateral escrow accounts, only for barters staked in a mint
+    #[account(mut)]
+    pub mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", barter.key().as_ref()],
+        bump
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(mut)]
+    pub initiator_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(mut)]
+    pub counterpart_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+
+    #[account(
+        init_if_needed,
+        payer = resolver,
+        space = Reputation::SIZE,
+        seeds = [b"reputation", global.key().as_ref(), barter.initiator.as_ref()],
+        bump
+    )]
+    pub initiator_reputation: Box<Account<'info, Reputation>>,
+
+    #[account(
+        init_if_needed,
+        payer = resolver,
+        space = Reputation::SIZE,
+        seeds = [b"reputation", global.key().as_ref(), barter.counterpart.as_ref()],
+        bump
+    )]
+    pub counterpart_reputation: Box<Account<'info, Reputation>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExpireBarter<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        seeds = [b"barter", global.key().as_ref(), barter.id.to_le_bytes().as_ref()],
+        bump = barter.bump
+    )]
+    pub barter: Account<'info, Barter>,
+
+    /// CHECK: Receives the initiator's share of the collateral, must match the barter
+    #[account(mut, address = barter.initiator @ ErrorCode::InvalidRecipient)]
+    pub initiator: UncheckedAccount<'info>,
+
+    /// CHECK: Receives the counterpart's share of the collateral, must match the barter; unused while open
+    #[account(
+        mut,
+        constraint = barter.status == BarterStatus::Open || counterpart.key() == barter.counterpart
+            @ ErrorCode::InvalidRecipient
+    )]
+    pub counterpart: UncheckedAccount<'info>,
+
+    // Collateral escrow accounts, only for barters staked in a mint
+    #[account(mut)]
+    pub mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", barter.key().as_ref()],
+        bump
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(mut)]
+    pub initiator_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(mut)]
+    pub counterpart_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+
+    pub caller: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ReleaseExpiredDeal<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        seeds = [b"deal", global.key().as_ref(), deal.id.to_le_bytes().as_ref()],
+        bump = deal.bump
+    )]
+    pub deal: Box<Account<'info, Deal>>,
+
+    #[account(
+        mut,
+        seeds = [b"need", global.key().as_ref(), deal.need_id.to_le_bytes().as_ref()],
+        bump = need.bump
+    )]
+    pub need: Box<Account<'info, Need>>,
+
+    /// CHECK: Provider account to receive payment, must match the deal
+    #[account(mut, address = deal.provider @ ErrorCode::InvalidRecipient)]
+    pub provider: UncheckedAccount<'info>,
+
+    /// CHECK: Fee treasury, must match global
+    #[account(mut, address = global.fee_treasury @ ErrorCode::InvalidTreasury)]
+    pub treasury: UncheckedAccount<'info>,
+
+    // Token escrow accounts, only for deals with a mint
+    pub mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", deal.key().as_ref()],
+        bump
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(mut)]
+    pub provider_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(mut)]
+    pub treasury_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+
+    #[account(
+        init_if_needed,
+        payer = caller,
+        space = Reputation::SIZE,
+        seeds = [b"reputation", global.key().as_ref(), deal.client.as_ref()],
+        bump
+    )]
+    pub client_reputation: Box<Account<'info, Reputation>>,
+
+    #[account(
+        init_if_needed,
+        payer = caller,
+        space = Reputation::SIZE,
+        seeds = [b"reputation", global.key().as_ref(), deal.provider.as_ref()],
+        bump
+    )]
+    pub provider_reputation: Box<Account<'info, Reputation>>,
+
+    #[account(mut)]
+    pub caller: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RefundExpiredDeal<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        seeds = [b"deal", global.key().as_ref(), deal.id.to_le_bytes().as_ref()],
+        bump = deal.bump
+    )]
+    pub deal: Box<Account<'info, Deal>>,
+
+    #[account(
+        mut,
+        seeds = [b"need", global.key().as_ref(), deal.need_id.to_le_bytes().as_ref()],
+        bump = need.bump
+    )]
+    pub need: Box<Account<'info, Need>>,
+
+    /// CHECK: Client account to receive refund, must match the deal
+    #[account(mut, address = deal.client @ ErrorCode::InvalidRecipient)]
+    pub client: UncheckedAccount<'info>,
+
+    // Token escrow accounts, only for deals with a mint
+    pub mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", deal.key().as_ref()],
+        bump
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(mut)]
+    pub client_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+
+    pub caller: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct MutualCancel<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        seeds = [b"deal", global.key().as_ref(), deal.id.to_le_bytes().as_ref()],
+        bump = deal.bump
+    )]
+    pub deal: Box<Account<'info, Deal>>,
+
+    #[account(
+        mut,
+        seeds = [b"need", global.key().as_ref(), deal.need_id.to_le_bytes().as_ref()],
+        bump = need.bump
+    )]
+    pub need: Box<Account<'info, Need>>,
+
+    /// CHECK: Client account to receive the refund, must match the deal
+    #[account(mut, address = deal.client @ ErrorCode::InvalidRecipient)]
+    pub client: UncheckedAccount<'info>,
+
+    /// CHECK: Provider account to receive the kill fee, must match the deal
+    #[account(mut, address = deal.provider @ ErrorCode::InvalidRecipient)]
+    pub provider: UncheckedAccount<'info>,
+
+    // Token escrow accounts, only for deals with a mint
+    pub mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", deal.key().as_ref()],
+        bump
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(mut)]
+    pub client_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(mut)]
+    pub provider_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+
+    pub caller: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFromDeal<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        has_one = provider @ ErrorCode::NotProvider,
+        seeds = [b"deal", global.key().as_ref(), deal.id.to_le_bytes().as_ref()],
+        bump = deal.bump
+    )]
+    pub deal: Box<Account<'info, Deal>>,
+
+    #[account(
+        mut,
+        seeds = [b"need", global.key().as_ref(), deal.need_id.to_le_bytes().as_ref()],
+        bump = need.bump
+    )]
+    pub need: Box<Account<'info, Need>>,
+
+    /// CHECK: Client account to receive the refund, must match the deal
+    #[account(mut, address = deal.client @ ErrorCode::InvalidRecipient)]
+    pub client: UncheckedAccount<'info>,
+
+    // Token escrow accounts, only for deals with a mint
+    pub mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", deal.key().as_ref()],
+        bump
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(mut)]
+    pub client_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+
+    #[account(
+        init_if_needed,
+        payer = provider,
+        space = Reputation::SIZE,
+        seeds = [b"reputation", global.key().as_ref(), provider.key().as_ref()],
+        bump
+    )]
+    pub provider_reputation: Box<Account<'info, Reputation>>,
+
+    #[account(mut)]
+    pub provider: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RateDeal<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        seeds = [b"deal", global.key().as_ref(), deal.id.to_le_bytes().as_ref()],
+        bump = deal.bump
+    )]
+    pub deal: Box<Account<'info, Deal>>,
+
+    /// CHECK: The other side of the deal from the rater
+    #[account(
+        constraint = deal.counterparty_of(&rater.key()) == Some(ratee.key()) @ ErrorCode::NotDealParticipant
+    )]
+    pub ratee: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = rater,
+        space = Reputation::SIZE,
+        seeds = [b"reputation", global.key().as_ref(), ratee.key().as_ref()],
+        bump
+    )]
+    pub ratee_reputation: Box<Account<'info, Reputation>>,
+
+    #[account(mut)]
+    pub rater: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseNeed<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        close = creator,
+        has_one = creator @ ErrorCode::NotNeedCreator,
+        seeds = [b"need", global.key().as_ref(), need.id.to_le_bytes().as_ref()],
+        bump = need.bump
+    )]
+    pub need: Account<'info, Need>,
+
+    #[account(mut)]
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseOffer<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        close = provider,
+        has_one = provider @ ErrorCode::NotProvider,
+        seeds = [b"offer", global.key().as_ref(), offer.id.to_le_bytes().as_ref()],
+        bump = offer.bump
+    )]
+    pub offer: Account<'info, Offer>,
+
+    #[account(mut)]
+    pub provider: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseDeal<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        close = client,
+        has_one = client @ ErrorCode::NotClient,
+        seeds = [b"deal", global.key().as_ref(), deal.id.to_le_bytes().as_ref()],
+        bump = deal.bump
+    )]
+    pub deal: Box<Account<'info, Deal>>,
+
+    #[account(mut)]
+    pub client: Signer<'info>,
+
+    // Token escrow accounts, only for deals with a mint
+    #[account(mut)]
+    pub mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", deal.key().as_ref()],
+        bump
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+}
+
+#[derive(Accounts)]
+pub struct CloseDisputeEvidence<'info> {
+    #[account(
+        mut,
+        close = opened_by,
+        has_one = opened_by @ ErrorCode::NotDealParticipant,
+        seeds = [b"evidence", evidence.deal.as_ref(), &[evidence.milestone]],
+        bump = evidence.bump
+    )]
+    pub evidence: Box<Account<'info, DisputeEvidence>>,
+
+    /// CHECK: The evidence's deal; may already be closed
+    #[account(address = evidence.deal)]
+    pub deal: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub opened_by: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseBarter<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        close = initiator,
+        has_one = initiator @ ErrorCode::BarterNotInitiator,
+        seeds = [b"barter", global.key().as_ref(), barter.id.to_le_bytes().as_ref()],
+        bump = barter.bump
+    )]
+    pub barter: Account<'info, Barter>,
+
+    #[account(mut)]
+    pub initiator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateBarterRing<'info> {
+    #[account(
+        mut,
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        init,
+        payer = initiator,
+        space = BarterRing::SIZE,
+        seeds = [b"barter_ring", global.key().as_ref(), global.ring_counter.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub ring: Account<'info, BarterRing>,
+
+    #[account(mut)]
+    pub initiator: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct JoinBarterRing<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        seeds = [b"barter_ring", global.key().as_ref(), ring.id.to_le_bytes().as_ref()],
+        bump = ring.bump
+    )]
+    pub ring: Account<'info, BarterRing>,
+
+    pub caller: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SubmitRingDelivery<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        seeds = [b"barter_ring", global.key().as_ref(), ring.id.to_le_bytes().as_ref()],
+        bump = ring.bump
+    )]
+    pub ring: Account<'info, BarterRing>,
+
+    pub caller: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ConfirmRingLink<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        seeds = [b"barter_ring", global.key().as_ref(), ring.id.to_le_bytes().as_ref()],
+        bump = ring.bump
+    )]
+    pub ring: Account<'info, BarterRing>,
+
+    pub caller: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelBarterRing<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        seeds = [b"barter_ring", global.key().as_ref(), ring.id.to_le_bytes().as_ref()],
+        bump = ring.bump
+    )]
+    pub ring: Account<'info, BarterRing>,
+
+    pub initiator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExpireBarterRing<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        seeds = [b"barter_ring", global.key().as_ref(), ring.id.to_le_bytes().as_ref()],
+        bump = ring.bump
+    )]
+    pub ring: Account<'info, BarterRing>,
+
+    pub caller: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseBarterRing<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
+    #[account(
+        mut,
+        close = initiator,
+        has_one = initiator @ ErrorCode::BarterNotInitiator,
+        seeds = [b"barter_ring", global.key().as_ref(), ring.id.to_le_bytes().as_ref()],
+        bump = ring.bump
     )]
-    pub need: Account<'info, Need>,
+    pub ring: Account<'info, BarterRing>,
 
     #[account(mut)]
-    pub creator: Signer<'info>,
-    pub system_program: Program<'info, System>,
+    pub initiator: Signer<'info>,
 }
 
 #[derive(Accounts)]
-#[instruction(need_id: u64)]
-pub struct CreateOffer<'info> {
-    #[account(mut)]
-    pub global: Account<'info, Global>,
-
+pub struct InitArbitratorPool<'info> {
     #[account(
-        seeds = [b"need", need_id.to_le_bytes().as_ref()],
-        bump = need.bump
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
     )]
-    pub need: Account<'info, Need>,
+    pub global: Account<'info, Global>,
 
     #[account(
         init,
-        payer = provider,
-        space = Offer::SIZE,
-        seeds = [b"offer", global.offer_counter.to_le_bytes().as_ref()],
+        payer = authority,
+        space = ArbitratorPool::SIZE,
+        seeds = [b"arbitrator_pool", global.key().as_ref()],
         bump
     )]
-    pub offer: Account<'info, Offer>,
+    pub pool: Account<'info, ArbitratorPool>,
 
     #[account(mut)]
-    pub provider: Signer<'info>,
+    pub authority: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct AcceptOffer<'info> {
-    #[account(mut)]
-    pub global: Account<'info, Global>,
-
+pub struct JoinArbitratorPool<'info> {
     #[account(
-        mut,
-        seeds = [b"need", need.id.to_le_bytes().as_ref()],
-        bump = need.bump
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
     )]
-    pub need: Account<'info, Need>,
+    pub global: Account<'info, Global>,
 
     #[account(
         mut,
-        seeds = [b"offer", offer.id.to_le_bytes().as_ref()],
-        bump = offer.bump
+        seeds = [b"arbitrator_pool", global.key().as_ref()],
+        bump = pool.bump
     )]
-    pub offer: Account<'info, Offer>,
+    pub pool: Account<'info, ArbitratorPool>,
 
     #[account(
         init,
-        payer = client,
-        space = Deal::SIZE,
-        seeds = [b"deal", global.deal_counter.to_le_bytes().as_ref()],
+        payer = wallet,
+        space = Arbitrator::SIZE,
+        seeds = [b"arbitrator", global.key().as_ref(), wallet.key().as_ref()],
         bump
     )]
-    pub deal: Account<'info, Deal>,
+    pub arbitrator: Account<'info, Arbitrator>,
 
     #[account(mut)]
-    pub client: Signer<'info>,
+    pub wallet: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct SubmitDelivery<'info> {
+pub struct LeaveArbitratorPool<'info> {
     #[account(
-        mut,
-        seeds = [b"deal", deal.id.to_le_bytes().as_ref()],
-        bump = deal.bump
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
     )]
-    pub deal: Account<'info, Deal>,
-
-    pub provider: Signer<'info>,
-}
+    pub global: Account<'info, Global>,
 
-#[derive(Accounts)]
-pub struct ConfirmDelivery<'info> {
     #[account(
         mut,
-        seeds = [b"deal", deal.id.to_le_bytes().as_ref()],
-        bump = deal.bump
+        seeds = [b"arbitrator_pool", global.key().as_ref()],
+        bump = pool.bump
     )]
-    pub deal: Account<'info, Deal>,
+    pub pool: Account<'info, ArbitratorPool>,
 
     #[account(
         mut,
-        seeds = [b"need", need.id.to_le_bytes().as_ref()],
-        bump = need.bump
+        has_one = wallet @ ErrorCode::NotJuror,
+        seeds = [b"arbitrator", global.key().as_ref(), wallet.key().as_ref()],
+        bump = arbitrator.bump
     )]
-    pub need: Account<'info, Need>,
+    pub arbitrator: Account<'info, Arbitrator>,
 
-    #[account(mut)]
-    pub client: Signer<'info>,
-    
-    /// CHECK: Provider account to receive payment
-    #[account(mut)]
-    pub provider: UncheckedAccount<'info>,
+    pub wallet: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct RaiseDispute<'info> {
+pub struct WithdrawArbitratorStake<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
     #[account(
         mut,
-        seeds = [b"deal", deal.id.to_le_bytes().as_ref()],
-        bump = deal.bump
+        close = wallet,
+        has_one = wallet @ ErrorCode::NotJuror,
+        seeds = [b"arbitrator", global.key().as_ref(), wallet.key().as_ref()],
+        bump = arbitrator.bump
     )]
-    pub deal: Account<'info, Deal>,
+    pub arbitrator: Account<'info, Arbitrator>,
 
-    pub caller: Signer<'info>,
+    #[account(mut)]
+    pub wallet: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct ResolveDispute<'info> {
+pub struct DrawJury<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
     pub global: Account<'info, Global>,
 
     #[account(
-        mut,
-        seeds = [b"deal", deal.id.to_le_bytes().as_ref()],
-        bump = deal.bump
+        seeds = [b"arbitrator_pool", global.key().as_ref()],
+        bump = pool.bump
     )]
-    pub deal: Account<'info, Deal>,
+    pub pool: Account<'info, ArbitratorPool>,
 
     #[account(
         mut,
-        seeds = [b"need", need.id.to_le_bytes().as_ref()],
-        bump = need.bump
+        seeds = [b"deal", global.key().as_ref(), deal.id.to_le_bytes().as_ref()],
+        bump = deal.bump
     )]
-    pub need: Account<'info, Need>,
+    pub deal: Box<Account<'info, Deal>>,
 
-    pub authority: Signer<'info>,
+    #[account(
+        init,
+        payer = caller,
+        space = Jury::SIZE,
+        seeds = [b"jury", deal.key().as_ref(), &[deal.current_milestone]],
+        bump
+    )]
+    pub jury: Box<Account<'info, Jury>>,
 
-    /// CHECK: Client account to receive refund
-    #[account(mut)]
-    pub client: UncheckedAccount<'info>,
+    /// CHECK: SlotHashes sysvar, read raw since it is too large to deserialize
+    #[account(address = slot_hashes::ID)]
+    pub slot_hashes: UncheckedAccount<'info>,
 
-    /// CHECK: Provider account to receive payment
     #[account(mut)]
-    pub provider: UncheckedAccount<'info>,
+    pub caller: Signer<'info>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct CancelNeed<'info> {
+pub struct LockJury<'info> {
     #[account(
-        mut,
-        seeds = [b"need", need.id.to_le_bytes().as_ref()],
-        bump = need.bump
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
     )]
-    pub need: Account<'info, Need>,
+    pub global: Account<'info, Global>,
 
-    pub creator: Signer<'info>,
-}
+    #[account(
+        seeds = [b"arbitrator_pool", global.key().as_ref()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, ArbitratorPool>,
+
+    #[account(
+        seeds = [b"deal", global.key().as_ref(), deal.id.to_le_bytes().as_ref()],
+        bump = deal.bump
+    )]
+    pub deal: Box<Account<'info, Deal>>,
 
-#[derive(Accounts)]
-pub struct CancelOffer<'info> {
     #[account(
         mut,
-        seeds = [b"offer", offer.id.to_le_bytes().as_ref()],
-        bump = offer.bump
+        seeds = [b"jury", deal.key().as_ref(), &[jury.milestone]],
+        bump = jury.bump,
+        constraint = deal.is_disputing(jury.milestone) @ ErrorCode::DealNotDisputed
     )]
-    pub offer: Account<'info, Offer>,
+    pub jury: Box<Account<'info, Jury>>,
 
-    pub provider: Signer<'info>,
+    pub caller: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct CreateBarter<'info> {
-    #[account(mut)]
+pub struct CommitVote<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
     pub global: Account<'info, Global>,
 
     #[account(
-        init,
-        payer = initiator,
-        space = Barter::SIZE,
-        seeds = [b"barter", global.barter_counter.to_le_bytes().as_ref()],
-        bump
+        seeds = [b"deal", global.key().as_ref(), deal.id.to_le_bytes().as_ref()],
+        bump = deal.bump
     )]
-    pub barter: Account<'info, Barter>,
+    pub deal: Box<Account<'info, Deal>>,
 
-    #[account(mut)]
-    pub initiator: Signer<'info>,
-    pub system_program: Program<'info, System>,
-}
-
-#[derive(Accounts)]
-pub struct AcceptBarter<'info> {
     #[account(
         mut,
-        seeds = [b"barter", barter.id.to_le_bytes().as_ref()],
-        bump = barter.bump
+        seeds = [b"jury", deal.key().as_ref(), &[jury.milestone]],
+        bump = jury.bump,
+        constraint = deal.is_disputing(jury.milestone) @ ErrorCode::DealNotDisputed
     )]
-    pub barter: Account<'info, Barter>,
+    pub jury: Box<Account<'info, Jury>>,
 
-    pub caller: Signer<'info>,
+    pub juror: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct SubmitBarterDelivery<'info> {
+pub struct RevealVote<'info> {
     #[account(
-        mut,
-        seeds = [b"barter", barter.id.to_le_bytes().as_ref()],
-        bump = barter.bump
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
     )]
-    pub barter: Account<'info, Barter>,
+    pub global: Account<'info, Global>,
 
-    pub caller: Signer<'info>,
-}
+    #[account(
+        seeds = [b"deal", global.key().as_ref(), deal.id.to_le_bytes().as_ref()],
+        bump = deal.bump
+    )]
+    pub deal: Box<Account<'info, Deal>>,
 
-#[derive(Accounts)]
-pub struct ConfirmBarterSide<'info> {
     #[account(
         mut,
-        seeds = [b"barter", barter.id.to_le_bytes().as_ref()],
-        bump = barter.bump
+        seeds = [b"jury", deal.key().as_ref(), &[jury.milestone]],
+        bump = jury.bump,
+        constraint = deal.is_disputing(jury.milestone) @ ErrorCode::DealNotDisputed
     )]
-    pub barter: Account<'info, Barter>,
+    pub jury: Box<Account<'info, Jury>>,
 
-    pub caller: Signer<'info>,
+    pub juror: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct CancelBarter<'info> {
+pub struct FinalizeJury<'info> {
+    #[account(
+        seeds = [b"global", global.global_id.to_le_bytes().as_ref()],
+        bump = global.bump
+    )]
+    pub global: Account<'info, Global>,
+
     #[account(
         mut,
-        seeds = [b"barter", barter.id.to_le_bytes().as_ref()],
-        bump = barter.bump
+        seeds = [b"arbitrator_pool", global.key().as_ref()],
+        bump = pool.bump
     )]
-    pub barter: Account<'info, Barter>,
+    pub pool: Account<'info, ArbitratorPool>,
 
-    pub initiator: Signer<'info>,
-}
+    #[account(
+        mut,
+        seeds = [b"deal", global.key().as_ref(), deal.id.to_le_bytes().as_ref()],
+        bump = deal.bump
+    )]
+    pub deal: Box<Account<'info, Deal>>,
 
-#[derive(Accounts)]
-pub struct DisputeBarter<'info> {
     #[account(
         mut,
-        seeds = [b"barter", barter.id.to_le_bytes().as_ref()],
-        bump = barter.bump
+        seeds = [b"jury", deal.key().as_ref(), &[jury.milestone]],
+        bump = jury.bump
     )]
-    pub barter: Account<'info, Barter>,
+    pub jury: Box<Account<'info, Jury>>,
+
+    /// CHECK: Fee treasury, receives slashed stakes; must match global
+    #[account(mut, address = global.fee_treasury @ ErrorCode::InvalidTreasury)]
+    pub treasury: UncheckedAccount<'info>,
 
     pub caller: Signer<'info>,
 }
@@ -727,83 +4446,271 @@ pub struct DisputeBarter<'info> {
 // Data structs
 #[account]
 pub struct Global {
+    pub global_id: u64,
+    /// `Pubkey::default()` once renounced
     pub authority: Pubkey,
+    /// Set by `propose_authority`, takes over on `accept_authority`
+    pub pending_authority: Option<Pubkey>,
     pub need_counter: u64,
     pub offer_counter: u64,
     pub deal_counter: u64,
     pub barter_counter: u64,
+    pub category_counter: u64,
+    pub ring_counter: u64,
+    /// Protocol fee taken from provider payouts, in basis points
+    pub fee_bps: u16,
+    pub fee_treasury: Pubkey,
+    /// Blocks new needs, offers and deals; settlement paths stay open
+    pub marketplace_paused: bool,
+    /// Blocks new and newly accepted barters
+    pub barter_paused: bool,
+    /// How long after a dispute is raised evidence is accepted and resolution waits; 0 disables
+    pub evidence_window_secs: i64,
     pub bump: u8,
 }
 
 impl Global {
-    pub const SIZE: usize = 8 + 32 + 8 + 8 + 8 + 8 + 1;
+    pub const SIZE: usize = 8 + 8 + 32 + (1 + 32) + 8 + 8 + 8 + 8 + 8 + 8 + 2 + 32 + 1 + 1 + 8 + 1;
+}
+
+#[account]
+pub struct Category {
+    pub id: u64,
+    /// Marketplace this account belongs to; part of its PDA seeds
+    pub global: Pubkey,
+    pub name: String,
+    /// Inactive categories accept no new needs; existing needs and deals are unaffected
+    pub active: bool,
+    /// Replaces `global.fee_bps` for deals accepted in this category
+    pub fee_bps: Option<u16>,
+    /// Smallest budget for SOL needs; token needs are priced in their own units and not checked
+    pub min_budget_lamports: u64,
+    pub bump: u8,
+}
+
+impl Category {
+    pub const SIZE: usize = 8 + 8 + 32 + (4 + 32) + 1 + (1 + 2) + 8 + 1;
+
+    pub fn allows_budget(&self, mint: Option<Pubkey>, budget_lamports: u64) -> bool {
+        mint.is_some() || budget_lamports >= self.min_budget_lamports
+    }
+}
+
+/// Marks a category name as taken within a marketplace
+#[account]
+pub struct CategoryName {
+    /// Zero until the name is claimed
+    pub global: Pubkey,
+    pub category_id: u64,
+    pub bump: u8,
+}
+
+impl CategoryName {
+    pub const SIZE: usize = 8 + 32 + 8 + 1;
 }
 
 #[account]
 pub struct Need {
     pub id: u64,
+    /// Marketplace this account belongs to; part of its PDA seeds
+    pub global: Pubkey,
     pub creator: Pubkey,
     pub title: String,
     pub description: String,
-    pub category: String,
+    pub category_id: u64,
     pub budget_lamports: u64,
+    /// When set, offers priced above `budget_lamports` are refused
+    pub budget_is_cap: bool,
+    /// Escrow mint; `None` means the need is priced in SOL
+    pub mint: Option<Pubkey>,
     pub status: NeedStatus,
     pub created_at: i64,
     pub deadline: Option<i64>,
+    /// Bumped on every `update_need`
+    pub revision: u32,
     pub bump: u8,
 }
 
 impl Need {
-    pub const SIZE: usize = 8 + 8 + 32 + (4 + 64) + (4 + 256) + (4 + 32) + 8 + 1 + 8 + (1 + 8) + 1;
+    pub const SIZE: usize = 8 + 8 + 32 + 32 + (4 + 64) + (4 + 256) + 8 + 8 + 1 + (1 + 32) + 1 + 8 + (1 + 8) + 4 + 1;
+
+    pub fn allows_price(&self, price: u64) -> bool {
+        !self.budget_is_cap || price <= self.budget_lamports
+    }
+
+    /// Puts the need back on the market after its deal fell through. Milestones already
+    /// paid to the provider come off the budget. Offers rejected when the deal was
+    /// accepted stay rejected, so the need starts over with no offers.
+    pub fn reopen(&mut self, paid_lamports: u64) {
+        self.status = NeedStatus::Open;
+        self.budget_lamports = self.budget_lamports.saturating_sub(paid_lamports);
+    }
 }
 
 #[account]
 pub struct Offer {
     pub id: u64,
+    /// Marketplace this account belongs to; part of its PDA seeds
+    pub global: Pubkey,
     pub need_id: u64,
     pub provider: Pubkey,
     pub price_lamports: u64,
+    pub mint: Option<Pubkey>,
     pub message: String,
+    /// Promised delivery time, counted from acceptance
+    pub delivery_secs: Option<i64>,
+    /// Staged payment amounts summing to `price_lamports`; empty for a single payment
+    pub milestones: Vec<u64>,
     pub status: OfferStatus,
     pub created_at: i64,
+    /// Bumped on every `update_offer` and `counter_offer`
+    pub revision: u32,
+    /// Number of counter-proposals made so far
+    pub negotiation_round: u8,
+    /// Side whose terms are on the table; the offer can only be accepted on the provider's terms
+    pub proposed_by: Pubkey,
     pub bump: u8,
 }
 
 impl Offer {
-    pub const SIZE: usize = 8 + 8 + 8 + 32 + 8 + (4 + 256) + 1 + 8 + 1;
+    pub const SIZE: usize = 8 + 8 + 32 + 8 + 32 + 8 + (1 + 32) + (4 + 256) + (1 + 8) + (4 + 8 * MAX_MILESTONES) + 1 + 8 + 4 + 1 + 32 + 1;
 }
 
 #[account]
 pub struct Deal {
     pub id: u64,
+    /// Marketplace this account belongs to; part of its PDA seeds
+    pub global: Pubkey,
     pub need_id: u64,
     pub offer_id: u64,
     pub client: Pubkey,
     pub provider: Pubkey,
+    /// Escrowed amount, in lamports or in base units of `mint`
     pub amount_lamports: u64,
+    /// Portion of `amount_lamports` that has left escrow, to either party
+    pub released_lamports: u64,
+    pub milestones: Vec<u64>,
+    /// Milestone that deliveries, confirmations and disputes currently apply to
+    pub current_milestone: u8,
+    /// Escrow mint; tokens sit in the `[b"vault", deal]` account when set
+    pub mint: Option<Pubkey>,
+    /// Protocol fee locked in from `Global` when the deal was created
+    pub fee_bps: u16,
     pub status: DealStatus,
     pub created_at: i64,
+    /// After this, anyone can refund an undelivered deal
+    pub delivery_deadline: Option<i64>,
+    /// After this, anyone can release a delivered deal to the provider
+    pub review_deadline: Option<i64>,
     pub delivery_hash: Option<String>,
     pub delivery_content: Option<String>,
     pub dispute_reason: Option<String>,
+    pub client_rated: bool,
+    pub provider_rated: bool,
+    /// Set once a jury is drawn; the authority can then no longer resolve alone
+    pub arbitrated: bool,
+    /// End of the current dispute's evidence window, if one applies
+    pub evidence_deadline: Option<i64>,
+    /// Pending `mutual_cancel` terms, waiting on the other party
+    pub cancel_proposal: Option<CancelProposal>,
     pub bump: u8,
 }
 
 impl Deal {
+    // +8 released_lamports, +4 (Vec len) +8 per milestone, +1 current_milestone
+    // +1 (Option) +32 (Pubkey) for mint, +2 fee_bps
+    // +1 (Option) +8 (i64) each for delivery_deadline and review_deadline
     // +1 (Option) +4 (String len) +512 (max content) for delivery_content
-    // +1 (Option) +4 (String len) +256 (max dispute_reason)
-    pub const SIZE: usize = 8 + 8 + 8 + 8 + 32 + 32 + 8 + 1 + 8 + (1 + 4 + 64) + (1 + 4 + 512) + (1 + 4 + 256) + 1;
+    // +1 (Option) +4 (String len) +256 (max dispute_reason), +1 +1 rated flags, +1 arbitrated, +1 +8 evidence_deadline, +1 cancel_proposal tag
+    pub const SIZE: usize = 8 + 8 + 32 + 8 + 8 + 32 + 32 + 8 + 8 + (4 + 8 * MAX_MILESTONES) + 1 + (1 + 32) + 2 + 1 + 8 + (1 + 8) + (1 + 8) + (1 + 4 + 64) + (1 + 4 + 512) + (1 + 4 + 256) + 1 + 1 + 1 + (1 + 8) + (1 + CancelProposal::SIZE) + 1;
+
+    pub fn fee_for(&self, amount: u64) -> u64 {
+        (amount as u128 * self.fee_bps as u128 / BPS_DENOMINATOR as u128) as u64
+    }
+
+    pub fn counterparty_of(&self, participant: &Pubkey) -> Option<Pubkey> {
+        if *participant == self.client {
+            Some(self.provider)
+        } else if *participant == self.provider {
+            Some(self.client)
+        } else {
+            None
+        }
+    }
+
+    pub fn remaining_escrow(&self) -> u64 {
+        self.amount_lamports - self.released_lamports
+    }
+
+    /// Whether `milestone` is the one currently under dispute
+    pub fn is_disputing(&self, milestone: u8) -> bool {
+        self.status == DealStatus::Disputed && self.current_milestone == milestone
+    }
+
+    pub fn is_last_milestone(&self) -> bool {
+        self.current_milestone as usize + 1 >= self.milestones.len()
+    }
+
+    /// Escrow due for the current milestone. The last one takes whatever is left,
+    /// which absorbs any transfer fee withheld when the escrow was funded.
+    pub fn current_milestone_amount(&self) -> u64 {
+        if self.is_last_milestone() {
+            self.remaining_escrow()
+        } else {
+            self.milestones[self.current_milestone as usize].min(self.remaining_escrow())
+        }
+    }
+
+    /// Records a paid-out milestone and moves on to the next one.
+    /// Returns true once the final milestone has been paid and the deal is complete.
+    pub fn complete_milestone(&mut self, amount: u64) -> bool {
+        self.released_lamports += amount;
+        if self.is_last_milestone() {
+            self.status = DealStatus::Completed;
+            return true;
+        }
+
+        self.current_milestone += 1;
+        self.status = DealStatus::InProgress;
+        self.review_deadline = None;
+        // Cancellation terms were agreed against the previous escrow balance
+        self.cancel_proposal = None;
+        // A jury or evidence window only ever covers the milestone it was opened for
+        self.arbitrated = false;
+        self.evidence_deadline = None;
+        false
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct CancelProposal {
+    pub proposed_by: Pubkey,
+    pub kill_fee_lamports: u64,
+    pub reopen_need: bool,
+}
+
+impl CancelProposal {
+    pub const SIZE: usize = 32 + 8 + 1;
 }
 
 #[account]
 pub struct Barter {
     pub id: u64,
+    /// Marketplace this account belongs to; part of its PDA seeds
+    pub global: Pubkey,
     pub initiator: Pubkey,
     pub counterpart: Pubkey,
     pub what_i_offer: String,
     pub what_i_want: String,
+    /// Stake each side posts, held until the barter settles; in `collateral_mint` units when set
+    pub collateral_lamports: u64,
+    pub collateral_mint: Option<Pubkey>,
     pub status: BarterStatus,
     pub created_at: i64,
+    /// An open barter can be expired once this passes without acceptance
+    pub accept_by: Option<i64>,
+    /// An in-progress barter can be expired once this passes; deliveries are refused after it
+    pub deliver_by: Option<i64>,
     pub side_a_delivery: Option<String>,
     pub side_a_hash: Option<String>,
     pub side_a_confirmed: bool,
@@ -811,19 +4718,222 @@ pub struct Barter {
     pub side_b_hash: Option<String>,
     pub side_b_confirmed: bool,
     pub dispute_reason: Option<String>,
+    /// May resolve the dispute alongside the global authority
+    pub arbiter: Option<Pubkey>,
+    /// Number of counter-proposals made so far
+    pub negotiation_round: u8,
+    /// Side whose terms are on the table; the barter can only be accepted on the initiator's terms
+    pub proposed_by: Pubkey,
+    pub bump: u8,
+}
+
+impl Barter {
+    // 8 discriminator + 8 id + 32 global + 32 initiator + 32 counterpart + (4+256) offer + (4+256) want
+    // + 8 collateral_lamports + (1+32) collateral_mint + 1 status + 8 created_at
+    // + (1+8) accept_by + (1+8) deliver_by
+    // + (1+4+512) side_a_delivery + (1+4+64) side_a_hash + 1 side_a_confirmed
+    // + (1+4+512) side_b_delivery + (1+4+64) side_b_hash + 1 side_b_confirmed
+    // + (1+4+256) dispute_reason + (1+32) arbiter + 1 negotiation_round + 32 proposed_by + 1 bump
+    pub const SIZE: usize = 8 + 8 + 32 + 32 + 32 + (4 + 256) + (4 + 256) + 8 + (1 + 32) + 1 + 8
+        + (1 + 8) + (1 + 8)
+        + (1 + 4 + 512) + (1 + 4 + 64) + 1
+        + (1 + 4 + 512) + (1 + 4 + 64) + 1
+        + (1 + 4 + 256) + (1 + 32) + 1 + 32 + 1;
+}
+
+#[account]
+pub struct BarterRing {
+    pub id: u64,
+    /// Marketplace this account belongs to; part of its PDA seeds
+    pub global: Pubkey,
+    pub initiator: Pubkey,
+    /// Participant `i` delivers to participant `(i + 1) % len`
+    pub participants: Vec<RingParticipant>,
+    pub status: BarterStatus,
+    pub created_at: i64,
+    /// Deadline for every participant's delivery; anyone can expire the ring after it
+    pub deliver_by: i64,
+    pub bump: u8,
+}
+
+impl BarterRing {
+    pub const SIZE: usize = 8 + 8 + 32 + 32 + (4 + MAX_RING_PARTICIPANTS * RingParticipant::SIZE) + 1 + 8 + 8 + 1;
+
+    pub fn position_of(&self, wallet: &Pubkey) -> Option<usize> {
+        self.participants.iter().position(|participant| participant.wallet == *wallet)
+    }
+
+    /// Index of the participant whose delivery goes to participant `index`
+    pub fn giver_to(&self, index: usize) -> usize {
+        (index + self.participants.len() - 1) % self.participants.len()
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct RingParticipant {
+    pub wallet: Pubkey,
+    pub gives: String,
+    pub gets: String,
+    pub joined: bool,
+    pub delivery_hash: Option<String>,
+    /// Set when the receiving participant confirms this participant's delivery
+    pub confirmed: bool,
+}
+
+impl RingParticipant {
+    pub const SIZE: usize = 32 + (4 + 128) + (4 + 128) + 1 + (1 + 4 + 64) + 1;
+}
+
+/// A participant's terms as proposed by the ring's initiator
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct RingTerms {
+    pub wallet: Pubkey,
+    pub gives: String,
+    pub gets: String,
+}
+
+#[account]
+pub struct ArbitratorPool {
+    pub global: Pubkey,
+    pub min_stake_lamports: u64,
+    /// Jury size, odd so a fully revealed vote can't tie
+    pub jurors_per_dispute: u8,
+    /// Share of a juror's stake slashed for voting against the verdict or not revealing
+    pub slash_bps: u16,
+    pub commit_secs: i64,
+    pub reveal_secs: i64,
+    /// Wallets eligible for selection; leaving or falling below the minimum stake removes them
+    pub members: Vec<Pubkey>,
+    pub bump: u8,
+}
+
+impl ArbitratorPool {
+    pub const SIZE: usize = 8 + 32 + 8 + 1 + 2 + 8 + 8 + (4 + MAX_POOL_MEMBERS * 32) + 1;
+}
+
+/// A juror's stake, held as lamports on this account
+#[account]
+pub struct Arbitrator {
+    pub global: Pubkey,
+    pub wallet: Pubkey,
+    pub stake_lamports: u64,
+    /// Juries this arbitrator was drawn for that aren't finalized yet
+    pub active_cases: u32,
+    pub slashed_lamports: u64,
+    /// Set by `leave_arbitrator_pool`; the stake can be withdrawn after it
+    pub unbonding_until: Option<i64>,
+    pub bump: u8,
+}
+
+impl Arbitrator {
+    pub const SIZE: usize = 8 + 32 + 32 + 8 + 4 + 8 + (1 + 8) + 1;
+}
+
+#[account]
+pub struct Jury {
+    pub global: Pubkey,
+    pub deal_id: u64,
+    /// Milestone under dispute; each disputed milestone gets its own jury
+    pub milestone: u8,
+    pub jurors: Vec<JurorVote>,
+    /// Last moment `lock_jury` can lock the jurors' stakes
+    pub lock_deadline: i64,
+    /// Both zero until `lock_jury` opens voting
+    pub commit_deadline: i64,
+    pub reveal_deadline: i64,
+    /// Majority outcome, set by `finalize_jury`
+    pub verdict: Option<DisputeResolution>,
+    /// Set by `lock_jury` once the jurors' stakes are locked
+    pub locked: bool,
+    pub finalized: bool,
+    pub bump: u8,
+}
+
+impl Jury {
+    pub const SIZE: usize = 8 + 32 + 8 + 1 + (4 + MAX_JURORS * JurorVote::SIZE) + 8 + 8 + 8 + (1 + DisputeResolution::SIZE) + 1 + 1 + 1;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct JurorVote {
+    pub wallet: Pubkey,
+    pub commitment: Option<[u8; 32]>,
+    pub vote: Option<DisputeResolution>,
+}
+
+impl JurorVote {
+    pub const SIZE: usize = 32 + (1 + 32) + (1 + DisputeResolution::SIZE);
+}
+
+#[account]
+pub struct DisputeEvidence {
+    pub global: Pubkey,
+    pub deal: Pubkey,
+    /// Milestone under dispute; each disputed milestone gets its own account
+    pub milestone: u8,
+    /// Paid the rent; gets it back on close
+    pub opened_by: Pubkey,
+    pub entries: Vec<EvidenceEntry>,
     pub bump: u8,
 }
 
-impl Barter {
-    // 8 discriminator + 8 id + 32 initiator + 32 counterpart + (4+256) offer + (4+256) want
-    // + 1 status + 8 created_at
-    // + (1+4+512) side_a_delivery + (1+4+64) side_a_hash + 1 side_a_confirmed
-    // + (1+4+512) side_b_delivery + (1+4+64) side_b_hash + 1 side_b_confirmed
-    // + (1+4+256) dispute_reason + 1 bump
-    pub const SIZE: usize = 8 + 8 + 32 + 32 + (4 + 256) + (4 + 256) + 1 + 8
-        + (1 + 4 + 512) + (1 + 4 + 64) + 1
-        + (1 + 4 + 512) + (1 + 4 + 64) + 1
-        + (1 + 4 + 256) + 1;
+impl DisputeEvidence {
+    pub const SIZE: usize = 8 + 32 + 32 + 1 + 32 + (4 + 2 * MAX_EVIDENCE_PER_PARTY * EvidenceEntry::SIZE) + 1;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct EvidenceEntry {
+    pub submitted_by: Pubkey,
+    pub content_hash: String,
+    pub uri: String,
+    pub submitted_at: i64,
+}
+
+impl EvidenceEntry {
+    pub const SIZE: usize = 32 + (4 + MAX_DELIVERY_HASH_LEN) + (4 + MAX_EVIDENCE_URI_LEN) + 8;
+}
+
+#[account]
+pub struct Reputation {
+    pub global: Pubkey,
+    pub wallet: Pubkey,
+    pub completed_deals: u64,
+    pub completed_barters: u64,
+    /// Settled volume of SOL deals; token deals are counted but not summed
+    pub volume_lamports: u64,
+    pub disputes_raised: u64,
+    pub disputes_lost: u64,
+    /// Deals the wallet backed out of as provider via `withdraw_from_deal`
+    pub withdrawals: u64,
+    pub rating_sum: u64,
+    pub rating_count: u64,
+    pub bump: u8,
+}
+
+impl Reputation {
+    pub const SIZE: usize = 8 + 32 + 32 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 1;
+
+    /// Reputation accounts are created on first use; fill in who they belong to.
+    pub fn ensure_initialized(&mut self, global: Pubkey, wallet: Pubkey, bump: u8) {
+        if self.wallet == Pubkey::default() {
+            self.global = global;
+            self.wallet = wallet;
+            self.bump = bump;
+        }
+    }
+
+    pub fn record_release(&mut self, deal: &Deal, amount: u64, completed: bool) {
+        if deal.mint.is_none() {
+            self.volume_lamports += amount;
+        }
+        if completed {
+            self.completed_deals += 1;
+        }
+    }
+
+    /// Average rating scaled by 100 (e.g. 450 = 4.5 stars)
+    pub fn average_rating_x100(&self) -> Option<u64> {
+        (self.rating_count > 0).then(|| self.rating_sum * 100 / self.rating_count)
+    }
 }
 
 // Enums
@@ -833,6 +4943,8 @@ pub enum NeedStatus {
     InProgress,
     Completed,
     Cancelled,
+    /// Its deal was settled by a split dispute resolution
+    PartiallyCompleted,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
@@ -847,6 +4959,43 @@ pub enum OfferStatus {
 pub enum DisputeResolution {
     RefundClient,
     PayProvider,
+    /// Settles the deal, paying `provider_bps` of the remaining escrow to the provider
+    /// and the rest back to the client
+    Split { provider_bps: u16 },
+}
+
+impl DisputeResolution {
+    // Variant tag + Split's provider_bps
+    pub const SIZE: usize = 1 + 2;
+
+    /// Splits can't give the provider more than the whole escrow
+    pub fn validate(&self) -> Result<()> {
+        if let DisputeResolution::Split { provider_bps } = self {
+            require!(*provider_bps as u64 <= BPS_DENOMINATOR, ErrorCode::InvalidSplit);
+        }
+        Ok(())
+    }
+}
+
+/// Side A is the barter initiator, side B the counterpart
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub enum BarterSide {
+    A,
+    B,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub enum BarterResolution {
+    /// No fault found; both stakes are returned
+    Cancel,
+    /// The given side forfeits its stake to the other
+    Slash { side: BarterSide },
+    /// The whole pool is divided, `initiator_bps` of it to the initiator
+    Split { initiator_bps: u16 },
+    /// Confirms the given side's delivery; completes the barter if the other side is confirmed too
+    SideFulfilled { side: BarterSide },
+    /// Both sides are confirmed and the barter completes; both stakes are returned
+    Complete,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
@@ -856,6 +5005,8 @@ pub enum DealStatus {
     Completed,
     Disputed,
     Cancelled,
+    /// Settled by a split dispute resolution
+    PartiallyCompleted,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
@@ -865,15 +5016,77 @@ pub enum BarterStatus {
     Completed,
     Disputed,
     Cancelled,
+    Expired,
 }
 
 // Events
+#[event]
+pub struct AuthorityProposed {
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+}
+
+#[event]
+pub struct AuthorityTransferred {
+    pub previous_authority: Pubkey,
+    pub new_authority: Pubkey,
+}
+
+#[event]
+pub struct AuthorityRenounced {
+    pub previous_authority: Pubkey,
+}
+
+#[event]
+pub struct PauseUpdated {
+    pub marketplace_paused: bool,
+    pub barter_paused: bool,
+}
+
+#[event]
+pub struct EvidenceWindowUpdated {
+    pub evidence_window_secs: i64,
+}
+
+#[event]
+pub struct FeeConfigUpdated {
+    pub fee_bps: u16,
+    pub fee_treasury: Pubkey,
+}
+
+#[event]
+pub struct CategoryCreated {
+    pub id: u64,
+    pub name: String,
+    pub fee_bps: Option<u16>,
+    pub min_budget_lamports: u64,
+}
+
+#[event]
+pub struct CategoryUpdated {
+    pub id: u64,
+    pub active: bool,
+    pub fee_bps: Option<u16>,
+    pub min_budget_lamports: u64,
+}
+
 #[event]
 pub struct NeedCreated {
     pub id: u64,
     pub creator: Pubkey,
     pub title: String,
+    pub category_id: u64,
+    pub budget_lamports: u64,
+    pub mint: Option<Pubkey>,
+}
+
+#[event]
+pub struct NeedUpdated {
+    pub id: u64,
+    pub revision: u32,
+    pub title: String,
     pub budget_lamports: u64,
+    pub deadline: Option<i64>,
 }
 
 #[event]
@@ -882,6 +5095,34 @@ pub struct OfferCreated {
     pub need_id: u64,
     pub provider: Pubkey,
     pub price_lamports: u64,
+    pub delivery_secs: Option<i64>,
+    pub milestones: Vec<u64>,
+}
+
+#[event]
+pub struct OfferCountered {
+    pub id: u64,
+    pub need_id: u64,
+    pub round: u8,
+    pub proposed_by: Pubkey,
+    pub price_lamports: u64,
+}
+
+#[event]
+pub struct CounterOfferAccepted {
+    pub id: u64,
+    pub round: u8,
+    pub price_lamports: u64,
+}
+
+#[event]
+pub struct OfferUpdated {
+    pub id: u64,
+    pub need_id: u64,
+    pub revision: u32,
+    pub price_lamports: u64,
+    pub delivery_secs: Option<i64>,
+    pub milestones: Vec<u64>,
 }
 
 #[event]
@@ -892,43 +5133,188 @@ pub struct DealCreated {
     pub client: Pubkey,
     pub provider: Pubkey,
     pub amount_lamports: u64,
+    pub mint: Option<Pubkey>,
+    pub delivery_deadline: Option<i64>,
+    pub milestones: Vec<u64>,
+    pub negotiation_round: u8,
 }
 
 #[event]
 pub struct DeliverySubmitted {
     pub deal_id: u64,
+    pub milestone: u8,
     pub provider: Pubkey,
     pub delivery_hash: String,
     pub delivery_content: String,
+    pub review_deadline: i64,
 }
 
 #[event]
 pub struct DeliveryConfirmed {
     pub deal_id: u64,
+    pub milestone: u8,
     pub client: Pubkey,
     pub provider: Pubkey,
     pub amount_lamports: u64,
+    pub fee_lamports: u64,
 }
 
 #[event]
 pub struct DisputeRaised {
     pub deal_id: u64,
+    pub milestone: u8,
     pub raised_by: Pubkey,
     pub reason: String,
+    pub evidence_deadline: Option<i64>,
+}
+
+#[event]
+pub struct EvidenceSubmitted {
+    pub deal_id: u64,
+    pub milestone: u8,
+    /// Position in the evidence account's entries
+    pub index: u8,
+    pub submitted_by: Pubkey,
+    pub content_hash: String,
+    pub uri: String,
+    pub submitted_at: i64,
 }
 
 #[event]
 pub struct DisputeResolved {
     pub deal_id: u64,
+    pub milestone: u8,
+    pub resolution: DisputeResolution,
+    /// Paid to each party by this resolution; the provider's is net of the fee
+    pub client_amount: u64,
+    pub provider_amount: u64,
+    pub fee_lamports: u64,
+}
+
+#[event]
+pub struct ArbitratorPoolInitialized {
+    pub min_stake_lamports: u64,
+    pub jurors_per_dispute: u8,
+    pub slash_bps: u16,
+    pub commit_secs: i64,
+    pub reveal_secs: i64,
+}
+
+#[event]
+pub struct ArbitratorJoined {
+    pub wallet: Pubkey,
+    pub stake_lamports: u64,
+}
+
+#[event]
+pub struct ArbitratorLeft {
+    pub wallet: Pubkey,
+    pub unbonding_until: i64,
+}
+
+#[event]
+pub struct ArbitratorWithdrawn {
+    pub wallet: Pubkey,
+    pub stake_lamports: u64,
+}
+
+#[event]
+pub struct JuryDrawn {
+    pub deal_id: u64,
+    pub jurors: Vec<Pubkey>,
+    pub lock_deadline: i64,
+}
+
+#[event]
+pub struct JuryLocked {
+    pub deal_id: u64,
+    pub commit_deadline: i64,
+    pub reveal_deadline: i64,
+}
+
+#[event]
+pub struct VoteCommitted {
+    pub deal_id: u64,
+    pub juror: Pubkey,
+}
+
+#[event]
+pub struct VoteRevealed {
+    pub deal_id: u64,
+    pub juror: Pubkey,
     pub resolution: DisputeResolution,
 }
 
+#[event]
+pub struct JuryFinalized {
+    pub deal_id: u64,
+    pub verdict: Option<DisputeResolution>,
+    pub slashed_lamports: u64,
+}
+
+#[event]
+pub struct DealAutoReleased {
+    pub deal_id: u64,
+    pub milestone: u8,
+    pub provider: Pubkey,
+    pub amount_lamports: u64,
+    pub fee_lamports: u64,
+    pub cranked_by: Pubkey,
+}
+
+#[event]
+pub struct MutualCancelProposed {
+    pub deal_id: u64,
+    pub proposed_by: Pubkey,
+    pub kill_fee_lamports: u64,
+    pub reopen_need: bool,
+}
+
+#[event]
+pub struct DealMutuallyCancelled {
+    pub deal_id: u64,
+    pub refunded_lamports: u64,
+    /// Paid to the provider in full
+    pub kill_fee_lamports: u64,
+    pub need_reopened: bool,
+}
+
+#[event]
+pub struct DealWithdrawn {
+    pub deal_id: u64,
+    pub provider: Pubkey,
+    pub refunded_lamports: u64,
+}
+
+#[event]
+pub struct DealAutoRefunded {
+    pub deal_id: u64,
+    pub client: Pubkey,
+    pub amount_lamports: u64,
+    pub cranked_by: Pubkey,
+}
+
+#[event]
+pub struct DealRated {
+    pub deal_id: u64,
+    pub rater: Pubkey,
+    pub ratee: Pubkey,
+    pub rating: u8,
+}
+
 #[event]
 pub struct NeedCancelled {
     pub id: u64,
     pub creator: Pubkey,
 }
 
+#[event]
+pub struct OfferRejected {
+    pub id: u64,
+    pub need_id: u64,
+    pub provider: Pubkey,
+}
+
 #[event]
 pub struct OfferCancelled {
     pub id: u64,
@@ -941,6 +5327,23 @@ pub struct BarterCreated {
     pub initiator: Pubkey,
     pub what_i_offer: String,
     pub what_i_want: String,
+    pub collateral_lamports: u64,
+    pub collateral_mint: Option<Pubkey>,
+}
+
+#[event]
+pub struct BarterCountered {
+    pub id: u64,
+    pub round: u8,
+    pub proposed_by: Pubkey,
+    pub what_i_offer: String,
+    pub what_i_want: String,
+}
+
+#[event]
+pub struct BarterCounterAccepted {
+    pub id: u64,
+    pub round: u8,
 }
 
 #[event]
@@ -972,6 +5375,63 @@ pub struct BarterCancelled {
     pub id: u64,
 }
 
+#[event]
+pub struct BarterRingCreated {
+    pub id: u64,
+    pub initiator: Pubkey,
+    pub participants: Vec<Pubkey>,
+    pub deliver_by: i64,
+}
+
+#[event]
+pub struct BarterRingJoined {
+    pub id: u64,
+    pub participant: Pubkey,
+}
+
+#[event]
+pub struct BarterRingStarted {
+    pub id: u64,
+}
+
+#[event]
+pub struct RingDeliverySubmitted {
+    pub ring_id: u64,
+    pub participant: Pubkey,
+    pub delivery_hash: String,
+}
+
+#[event]
+pub struct RingLinkConfirmed {
+    pub ring_id: u64,
+    pub giver: Pubkey,
+    pub confirmed_by: Pubkey,
+}
+
+#[event]
+pub struct BarterRingCompleted {
+    pub id: u64,
+}
+
+#[event]
+pub struct BarterRingCancelled {
+    pub id: u64,
+}
+
+#[event]
+pub struct BarterRingExpired {
+    pub id: u64,
+    pub previous_status: BarterStatus,
+}
+
+#[event]
+pub struct BarterExpired {
+    pub id: u64,
+    pub previous_status: BarterStatus,
+    pub initiator_amount: u64,
+    pub counterpart_amount: u64,
+}
+
 #[event]
 pub struct BarterDisputed {
     pub id: u64,
@@ -979,6 +5439,69 @@ pub struct BarterDisputed {
     pub reason: String,
 }
 
+#[event]
+pub struct BarterArbiterAssigned {
+    pub id: u64,
+    pub arbiter: Pubkey,
+}
+
+#[event]
+pub struct BarterDisputeResolved {
+    pub id: u64,
+    pub resolved_by: Pubkey,
+    pub resolution: BarterResolution,
+    pub status: BarterStatus,
+    pub initiator_amount: u64,
+    pub counterpart_amount: u64,
+}
+
+#[event]
+pub struct NeedArchived {
+    pub id: u64,
+    pub creator: Pubkey,
+    pub category_id: u64,
+    pub budget_lamports: u64,
+    pub mint: Option<Pubkey>,
+    pub status: NeedStatus,
+    pub created_at: i64,
+}
+
+#[event]
+pub struct OfferArchived {
+    pub id: u64,
+    pub need_id: u64,
+    pub provider: Pubkey,
+    pub price_lamports: u64,
+    pub status: OfferStatus,
+    pub created_at: i64,
+}
+
+#[event]
+pub struct DealArchived {
+    pub id: u64,
+    pub need_id: u64,
+    pub offer_id: u64,
+    pub client: Pubkey,
+    pub provider: Pubkey,
+    pub amount_lamports: u64,
+    pub released_lamports: u64,
+    pub mint: Option<Pubkey>,
+    pub status: DealStatus,
+    pub delivery_hash: Option<String>,
+    pub created_at: i64,
+}
+
+#[event]
+pub struct BarterArchived {
+    pub id: u64,
+    pub initiator: Pubkey,
+    pub counterpart: Pubkey,
+    pub status: BarterStatus,
+    pub side_a_hash: Option<String>,
+    pub side_b_hash: Option<String>,
+    pub created_at: i64,
+}
+
 // Errors
 #[error_code]
 pub enum ErrorCode {
@@ -996,13 +5519,13 @@ pub enum ErrorCode {
     DeliveryNotSubmitted,
     #[msg("Not the client")]
     NotClient,
-    #[msg("Delivery content exceeds 512 characters")]
+    #[msg("Delivery content exceeds 512 bytes")]
     DeliveryContentTooLong,
     #[msg("Deal cannot be disputed in current status")]
     DealNotDisputable,
     #[msg("Not a participant in this deal")]
     NotDealParticipant,
-    #[msg("Dispute reason exceeds 256 characters")]
+    #[msg("Dispute reason exceeds 256 bytes")]
     DisputeReasonTooLong,
     #[msg("Deal is not disputed")]
     DealNotDisputed,
@@ -1016,9 +5539,9 @@ pub enum ErrorCode {
     NotBarterParticipant,
     #[msg("Cannot accept your own barter")]
     CannotAcceptOwnBarter,
-    #[msg("Barter offer description too long")]
+    #[msg("Barter offer description exceeds 256 bytes")]
     BarterOfferTooLong,
-    #[msg("Barter want description too long")]
+    #[msg("Barter want description exceeds 256 bytes")]
     BarterWantTooLong,
     #[msg("Wrong barter target")]
     WrongBarterTarget,
@@ -1026,4 +5549,162 @@ pub enum ErrorCode {
     DeliveryNotReady,
     #[msg("Not the barter initiator")]
     BarterNotInitiator,
+    #[msg("Token mint does not match the escrow mint")]
+    MintMismatch,
+    #[msg("Token accounts are required for a token-denominated deal")]
+    TokenAccountsMissing,
+    #[msg("Token account is not owned by the recipient")]
+    InvalidTokenAccount,
+    #[msg("Fee exceeds the maximum allowed")]
+    FeeTooHigh,
+    #[msg("Category exceeds 32 bytes")]
+    CategoryTooLong,
+    #[msg("Treasury does not match the global fee treasury")]
+    InvalidTreasury,
+    #[msg("No authority handover is pending")]
+    NoPendingAuthority,
+    #[msg("Not the pending authority")]
+    NotPendingAuthority,
+    #[msg("Program is paused")]
+    Paused,
+    #[msg("Delivery window must be positive")]
+    InvalidDeliveryWindow,
+    #[msg("Delivery deadline has passed")]
+    DeliveryDeadlinePassed,
+    #[msg("Delivery deadline has not passed")]
+    DeliveryDeadlineNotReached,
+    #[msg("Review window is still open")]
+    ReviewWindowOpen,
+    #[msg("Milestones must be non-zero, at most 8, and sum to the price")]
+    InvalidMilestones,
+    #[msg("Deal is not finished")]
+    DealNotFinished,
+    #[msg("Rating must be between 1 and 5")]
+    InvalidRating,
+    #[msg("Deal already rated by this side")]
+    AlreadyRated,
+    #[msg("Account is not in a terminal state")]
+    NotClosable,
+    #[msg("Offer was not made on this need")]
+    OfferNeedMismatch,
+    #[msg("Remaining account is not a competing offer on this need")]
+    InvalidCompetingOffer,
+    #[msg("Offer price exceeds the need's budget cap")]
+    OverBudget,
+    #[msg("Offer price exceeds the expected maximum")]
+    PriceAboveExpected,
+    #[msg("Payout recipient does not match the deal")]
+    InvalidRecipient,
+    #[msg("Need does not belong to this deal")]
+    DealNeedMismatch,
+    #[msg("Title exceeds 64 bytes")]
+    TitleTooLong,
+    #[msg("Description exceeds 256 bytes")]
+    DescriptionTooLong,
+    #[msg("Message exceeds 256 bytes")]
+    MessageTooLong,
+    #[msg("Delivery hash exceeds 64 bytes")]
+    DeliveryHashTooLong,
+    #[msg("Category name must be lowercase letters, digits or dashes")]
+    InvalidCategoryName,
+    #[msg("Category is not active")]
+    CategoryInactive,
+    #[msg("Budget is below the category minimum")]
+    BelowCategoryMinimum,
+    #[msg("A collateral mint needs a non-zero collateral amount")]
+    InvalidCollateral,
+    #[msg("Barter is not disputed")]
+    BarterNotDisputed,
+    #[msg("Split must be at most 10000 bps")]
+    InvalidSplit,
+    #[msg("Only the global authority or the assigned arbiter can resolve this barter")]
+    NotBarterResolver,
+    #[msg("Barter deadlines must be in the future, with deliver-by after accept-by")]
+    InvalidBarterDeadline,
+    #[msg("Barter acceptance window has passed")]
+    BarterExpired,
+    #[msg("Barter has no deadline that has passed")]
+    BarterNotExpired,
+    #[msg("Barter rings need between 3 and 5 participants")]
+    InvalidRingSize,
+    #[msg("Ring initiator must be the first participant")]
+    RingInitiatorNotFirst,
+    #[msg("Ring give/get terms exceed 128 bytes")]
+    RingTermsTooLong,
+    #[msg("Wallet appears more than once in the ring")]
+    DuplicateRingParticipant,
+    #[msg("Already joined this ring")]
+    AlreadyJoinedRing,
+    #[msg("Not the need creator or provider of this offer")]
+    NotOfferParticipant,
+    #[msg("Cannot counter your own proposal")]
+    CannotCounterOwnProposal,
+    #[msg("Negotiation round limit reached")]
+    TooManyRounds,
+    #[msg("A counter-proposal is awaiting the other side")]
+    CounterPending,
+    #[msg("No counter-proposal to accept")]
+    NoCounterToAccept,
+    #[msg("Jury size must be odd and at most 5, slash at most 10000 bps, windows positive")]
+    InvalidPoolConfig,
+    #[msg("Stake is below the pool minimum")]
+    StakeTooLow,
+    #[msg("Arbitrator pool is full")]
+    PoolFull,
+    #[msg("Already leaving the arbitrator pool")]
+    AlreadyUnbonding,
+    #[msg("Stake is still locked")]
+    StakeLocked,
+    #[msg("Not enough eligible arbitrators in the pool")]
+    NotEnoughArbitrators,
+    #[msg("A jury has already been drawn for this deal")]
+    JuryAlreadyDrawn,
+    #[msg("Not a juror on this dispute")]
+    NotJuror,
+    #[msg("Commit window has closed")]
+    CommitWindowClosed,
+    #[msg("Commit window is still open")]
+    CommitWindowOpen,
+    #[msg("Reveal window has closed")]
+    RevealWindowClosed,
+    #[msg("Reveal window is still open")]
+    RevealWindowOpen,
+    #[msg("Revealed vote does not match the commitment")]
+    CommitmentMismatch,
+    #[msg("Jury is already finalized")]
+    JuryFinalized,
+    #[msg("Remaining accounts must be the jurors' arbitrator accounts, in order")]
+    InvalidArbitratorAccount,
+    #[msg("Deal is under arbitration; pass its jury")]
+    JuryRequired,
+    #[msg("Resolution does not match the jury's verdict")]
+    VerdictMismatch,
+    #[msg("Evidence window must not be negative")]
+    InvalidEvidenceWindow,
+    #[msg("Evidence window is still open")]
+    EvidenceWindowOpen,
+    #[msg("Evidence window has closed")]
+    EvidenceWindowClosed,
+    #[msg("Evidence URI exceeds 200 bytes")]
+    EvidenceUriTooLong,
+    #[msg("Evidence limit reached for this dispute")]
+    TooMuchEvidence,
+    #[msg("Only an in-progress or delivered deal can be cancelled")]
+    DealNotCancellable,
+    #[msg("Kill fee exceeds the remaining escrow")]
+    InvalidKillFee,
+    #[msg("Deadline must be in the future")]
+    InvalidDeadline,
+    #[msg("Delivery has already been confirmed")]
+    DeliveryAlreadyConfirmed,
+    #[msg("Jury stakes are not locked yet")]
+    JuryNotLocked,
+    #[msg("Jury stakes are already locked")]
+    JuryAlreadyLocked,
+    #[msg("Jury was not locked in time")]
+    JuryLockExpired,
+    #[msg("A category with this name already exists")]
+    CategoryNameTaken,
+    #[msg("Fee treasury must hold a rent-exempt balance")]
+    TreasuryNotRentExempt,
 }
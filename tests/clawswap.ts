@@ -1,7 +1,18 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
 import { Clawswap } from "../target/types/clawswap";
+import {
+  ExtensionType,
+  TOKEN_2022_PROGRAM_ID,
+  createAssociatedTokenAccount,
+  createInitializeMintInstruction,
+  createInitializeTransferFeeConfigInstruction,
+  getAccount,
+  getMintLen,
+  mintTo,
+} from "@solana/spl-token";
 import { expect } from "chai";
+import { createHash } from "crypto";
 
 describe("clawswap", () => {
   // Configure the client to use the local cluster.
@@ -17,10 +28,76 @@ describe("clawswap", () => {
   const providerAccount = anchor.web3.Keypair.generate();
   
   let globalPda: anchor.web3.PublicKey;
+  let categoryPda: anchor.web3.PublicKey;
   let needPda: anchor.web3.PublicKey;
   let offerPda: anchor.web3.PublicKey;
   let dealPda: anchor.web3.PublicKey;
 
+  // PDAs of counter-indexed accounts: [seed, global_pda, id]
+  const findPda = (seed: string, id: anchor.BN) =>
+    anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from(seed), globalPda.toBuffer(), id.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    )[0];
+
+  // Name markers are seeded by the name's hash, under the global they belong to
+  const categoryNamePda = (name: string, global: anchor.web3.PublicKey = globalPda) =>
+    anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("category_name"), global.toBuffer(), createHash("sha256").update(name).digest()],
+      program.programId
+    )[0];
+
+  // Asserts that `promise` fails with the given Anchor error code
+  const expectError = async (promise: Promise<unknown>, code: string) => {
+    let error: any;
+    try {
+      await promise;
+    } catch (err) {
+      error = err;
+    }
+    expect(error, `expected ${code}`).to.exist;
+    expect(error.error?.errorCode?.code).to.equal(code);
+  };
+
+  const sleep = (ms: number) => new Promise((resolve) => setTimeout(resolve, ms));
+
+  const chainTime = async () => {
+    const slot = await provider.connection.getSlot();
+    return (await provider.connection.getBlockTime(slot))!;
+  };
+
+  // Posts a need, has `providerAccount` offer `price` on it and accepts the offer
+  const openDeal = async (title: string, price: number, milestones: number[] = [], deliverySecs: number | null = null) => {
+    const global = await program.account.global.fetch(globalPda);
+    const need = findPda("need", global.needCounter);
+    const offer = findPda("offer", global.offerCounter);
+    const deal = findPda("deal", global.dealCounter);
+
+    await program.methods
+      .createNeed(title, "Test fixture", new anchor.BN(price), null, false)
+      .accounts({ global: globalPda, need, category: categoryPda, creator: creator.publicKey })
+      .signers([creator])
+      .rpc();
+    await program.methods
+      .createOffer(
+        global.needCounter,
+        new anchor.BN(price),
+        "Test fixture",
+        deliverySecs === null ? null : new anchor.BN(deliverySecs),
+        milestones.map((n) => new anchor.BN(n))
+      )
+      .accounts({ global: globalPda, need, offer, provider: providerAccount.publicKey })
+      .signers([providerAccount])
+      .rpc();
+    await program.methods
+      .acceptOffer(new anchor.BN(price))
+      .accounts({ global: globalPda, need, offer, category: categoryPda, deal, client: creator.publicKey })
+      .signers([creator])
+      .rpc();
+
+    return { need, offer, deal };
+  };
+
   before(async () => {
     // Airdrop SOL to test accounts
     await provider.connection.requestAirdrop(creator.publicKey, 2 * anchor.web3.LAMPORTS_PER_SOL);
@@ -56,10 +133,44 @@ describe("clawswap", () => {
     }
   });
 
+  it("Create a category", async () => {
+    const categoryId = new anchor.BN(0);
+    [categoryPda] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("category"), globalPda.toBuffer(), categoryId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+
+    await program.methods
+      .createCategory("development", null, new anchor.BN(0))
+      .accounts({
+        global: globalPda,
+        category: categoryPda,
+        categoryName: categoryNamePda("development"),
+        authority: authority.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .rpc();
+
+    const categoryAccount = await program.account.category.fetch(categoryPda);
+    expect(categoryAccount.name).to.equal("development");
+    expect(categoryAccount.active).to.equal(true);
+    expect(categoryAccount.feeBps).to.equal(null);
+  });
+
+  it("Disable the evidence window so fixtures can resolve disputes immediately", async () => {
+    await program.methods
+      .setEvidenceWindow(new anchor.BN(0))
+      .accounts({ global: globalPda, authority: authority.publicKey })
+      .rpc();
+
+    const globalAccount = await program.account.global.fetch(globalPda);
+    expect(globalAccount.evidenceWindowSecs.toNumber()).to.equal(0);
+  });
+
   it("Create a need", async () => {
     const needId = new anchor.BN(0);
     [needPda] = anchor.web3.PublicKey.findProgramAddressSync(
-      [Buffer.from("need"), needId.toArrayLike(Buffer, "le", 8)],
+      [Buffer.from("need"), globalPda.toBuffer(), needId.toArrayLike(Buffer, "le", 8)],
       program.programId
     );
 
@@ -68,13 +179,14 @@ describe("clawswap", () => {
         .createNeed(
           "AI Code Review",
           "Need someone to review my Rust smart contract code for security vulnerabilities",
-          "development",
           new anchor.BN(0.1 * anchor.web3.LAMPORTS_PER_SOL), // 0.1 SOL budget
-          null
+          null,
+          true // budget is a hard cap
         )
         .accounts({
           global: globalPda,
           need: needPda,
+          category: categoryPda,
           creator: creator.publicKey,
           systemProgram: anchor.web3.SystemProgram.programId,
         })
@@ -88,6 +200,7 @@ describe("clawswap", () => {
       expect(needAccount.id.toNumber()).to.equal(0);
       expect(needAccount.creator.toString()).to.equal(creator.publicKey.toString());
       expect(needAccount.title).to.equal("AI Code Review");
+      expect(needAccount.categoryId.toNumber()).to.equal(0);
       expect(needAccount.status).to.deep.equal({ open: {} });
     } catch (error) {
       console.error("Create need error:", error);
@@ -98,7 +211,7 @@ describe("clawswap", () => {
   it("Create an offer", async () => {
     const offerId = new anchor.BN(0);
     [offerPda] = anchor.web3.PublicKey.findProgramAddressSync(
-      [Buffer.from("offer"), offerId.toArrayLike(Buffer, "le", 8)],
+      [Buffer.from("offer"), globalPda.toBuffer(), offerId.toArrayLike(Buffer, "le", 8)],
       program.programId
     );
 
@@ -107,7 +220,9 @@ describe("clawswap", () => {
         .createOffer(
           new anchor.BN(0), // need_id
           new anchor.BN(0.05 * anchor.web3.LAMPORTS_PER_SOL), // 0.05 SOL price
-          "I'm an expert Rust developer. Can deliver within 24h."
+          "I'm an expert Rust developer. Can deliver within 24h.",
+          new anchor.BN(24 * 60 * 60), // delivery window
+          [] // single payment, no milestones
         )
         .accounts({
           global: globalPda,
@@ -136,17 +251,18 @@ describe("clawswap", () => {
   it("Accept offer and create deal", async () => {
     const dealId = new anchor.BN(0);
     [dealPda] = anchor.web3.PublicKey.findProgramAddressSync(
-      [Buffer.from("deal"), dealId.toArrayLike(Buffer, "le", 8)],
+      [Buffer.from("deal"), globalPda.toBuffer(), dealId.toArrayLike(Buffer, "le", 8)],
       program.programId
     );
 
     try {
       const tx = await program.methods
-        .acceptOffer()
+        .acceptOffer(new anchor.BN(0.05 * anchor.web3.LAMPORTS_PER_SOL)) // expected max price
         .accounts({
           global: globalPda,
           need: needPda,
           offer: offerPda,
+          category: categoryPda,
           deal: dealPda,
           client: creator.publicKey,
           systemProgram: anchor.web3.SystemProgram.programId,
@@ -175,8 +291,9 @@ describe("clawswap", () => {
   it("Submit delivery", async () => {
     try {
       const tx = await program.methods
-        .submitDelivery("QmXxX123...") // IPFS hash mock
+        .submitDelivery("QmXxX123...", "Review report: no critical issues found") // IPFS hash mock
         .accounts({
+          global: globalPda,
           deal: dealPda,
           provider: providerAccount.publicKey,
         })
@@ -202,10 +319,12 @@ describe("clawswap", () => {
       const tx = await program.methods
         .confirmDelivery()
         .accounts({
+          global: globalPda,
           deal: dealPda,
           need: needPda,
           client: creator.publicKey,
           provider: providerAccount.publicKey,
+          treasury: authority.publicKey,
         })
         .signers([creator])
         .rpc();
@@ -229,4 +348,2567 @@ describe("clawswap", () => {
       throw error;
     }
   });
-});
\ No newline at end of file
+
+  describe("token escrow", () => {
+    // Token-2022 mint withholding 1% of every transfer
+    const mint = anchor.web3.Keypair.generate();
+    const feeBps = 100;
+    const price = 1_000_000;
+
+    let clientTokens: anchor.web3.PublicKey;
+    let providerTokens: anchor.web3.PublicKey;
+
+    const tokenBalance = async (account: anchor.web3.PublicKey) =>
+      Number((await getAccount(provider.connection, account, undefined, TOKEN_2022_PROGRAM_ID)).amount);
+
+    const vaultPda = (deal: anchor.web3.PublicKey) =>
+      anchor.web3.PublicKey.findProgramAddressSync([Buffer.from("vault"), deal.toBuffer()], program.programId)[0];
+
+    // Same as openDeal, with the escrow in `mint`
+    const openTokenDeal = async (deliverySecs: number | null) => {
+      const global = await program.account.global.fetch(globalPda);
+      const need = findPda("need", global.needCounter);
+      const offer = findPda("offer", global.offerCounter);
+      const deal = findPda("deal", global.dealCounter);
+
+      await program.methods
+        .createNeed("Token need", "Paid in a transfer-fee mint", new anchor.BN(price), null, false)
+        .accounts({ global: globalPda, need, category: categoryPda, mint: mint.publicKey, creator: creator.publicKey })
+        .signers([creator])
+        .rpc();
+      await program.methods
+        .createOffer(global.needCounter, new anchor.BN(price), "Token offer", deliverySecs === null ? null : new anchor.BN(deliverySecs), [])
+        .accounts({ global: globalPda, need, offer, provider: providerAccount.publicKey })
+        .signers([providerAccount])
+        .rpc();
+      await program.methods
+        .acceptOffer(new anchor.BN(price))
+        .accounts({
+          global: globalPda,
+          need,
+          offer,
+          category: categoryPda,
+          deal,
+          client: creator.publicKey,
+          mint: mint.publicKey,
+          vault: vaultPda(deal),
+          clientTokenAccount: clientTokens,
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+        })
+        .signers([creator])
+        .rpc();
+
+      return { need, deal };
+    };
+
+    const closeDeal = (deal: anchor.web3.PublicKey) =>
+      program.methods
+        .closeDeal(false)
+        .accounts({
+          global: globalPda,
+          deal,
+          client: creator.publicKey,
+          mint: mint.publicKey,
+          vault: vaultPda(deal),
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+        })
+        .signers([creator])
+        .rpc();
+
+    before(async () => {
+      const payer = authority.payer;
+      const mintLen = getMintLen([ExtensionType.TransferFeeConfig]);
+      const createMintTx = new anchor.web3.Transaction().add(
+        anchor.web3.SystemProgram.createAccount({
+          fromPubkey: payer.publicKey,
+          newAccountPubkey: mint.publicKey,
+          space: mintLen,
+          lamports: await provider.connection.getMinimumBalanceForRentExemption(mintLen),
+          programId: TOKEN_2022_PROGRAM_ID,
+        }),
+        createInitializeTransferFeeConfigInstruction(
+          mint.publicKey,
+          payer.publicKey,
+          payer.publicKey,
+          feeBps,
+          BigInt(price),
+          TOKEN_2022_PROGRAM_ID
+        ),
+        createInitializeMintInstruction(mint.publicKey, 0, payer.publicKey, null, TOKEN_2022_PROGRAM_ID)
+      );
+      await provider.sendAndConfirm(createMintTx, [mint]);
+
+      clientTokens = await createAssociatedTokenAccount(
+        provider.connection, payer, mint.publicKey, creator.publicKey, undefined, TOKEN_2022_PROGRAM_ID
+      );
+      providerTokens = await createAssociatedTokenAccount(
+        provider.connection, payer, mint.publicKey, providerAccount.publicKey, undefined, TOKEN_2022_PROGRAM_ID
+      );
+      await mintTo(
+        provider.connection, payer, mint.publicKey, clientTokens, payer, 10 * price, [], undefined, TOKEN_2022_PROGRAM_ID
+      );
+    });
+
+    it("escrows what arrives after the transfer fee, releases it and closes the vault", async () => {
+      const { need, deal } = await openTokenDeal(null);
+
+      // 1% of the deposit is withheld in the vault
+      const escrowed = price - price / 100;
+      let dealAccount = await program.account.deal.fetch(deal);
+      expect(dealAccount.mint.toString()).to.equal(mint.publicKey.toString());
+      expect(dealAccount.amountLamports.toNumber()).to.equal(escrowed);
+      expect(await tokenBalance(vaultPda(deal))).to.equal(escrowed);
+
+      await program.methods
+        .submitDelivery("QmToken", "Token delivery")
+        .accounts({ global: globalPda, deal, provider: providerAccount.publicKey })
+        .signers([providerAccount])
+        .rpc();
+      const providerBefore = await tokenBalance(providerTokens);
+      await program.methods
+        .confirmDelivery()
+        .accounts({
+          global: globalPda,
+          deal,
+          need,
+          client: creator.publicKey,
+          provider: providerAccount.publicKey,
+          treasury: authority.publicKey,
+          mint: mint.publicKey,
+          vault: vaultPda(deal),
+          providerTokenAccount: providerTokens,
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+        })
+        .signers([creator])
+        .rpc();
+
+      // The payout is itself a transfer, so another 1% is withheld on the way out
+      dealAccount = await program.account.deal.fetch(deal);
+      expect(dealAccount.status).to.deep.equal({ completed: {} });
+      expect((await tokenBalance(providerTokens)) - providerBefore).to.equal(escrowed - Math.ceil(escrowed / 100));
+      expect(await tokenBalance(vaultPda(deal))).to.equal(0);
+
+      // The vault still holds the fee withheld on deposit, which is harvested before it closes
+      await closeDeal(deal);
+      expect(await provider.connection.getAccountInfo(vaultPda(deal))).to.equal(null);
+      expect(await provider.connection.getAccountInfo(deal)).to.equal(null);
+    });
+
+    it("refunds an expired token deal and closes its vault", async () => {
+      const { need, deal } = await openTokenDeal(1);
+      const escrowed = price - price / 100;
+
+      await sleep(3_000);
+      const clientBefore = await tokenBalance(clientTokens);
+      await program.methods
+        .refundExpiredDeal()
+        .accounts({
+          global: globalPda,
+          deal,
+          need,
+          client: creator.publicKey,
+          mint: mint.publicKey,
+          vault: vaultPda(deal),
+          clientTokenAccount: clientTokens,
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+          caller: authority.publicKey,
+        })
+        .rpc();
+
+      expect((await program.account.deal.fetch(deal)).status).to.deep.equal({ cancelled: {} });
+      expect((await tokenBalance(clientTokens)) - clientBefore).to.equal(escrowed - Math.ceil(escrowed / 100));
+
+      await closeDeal(deal);
+      expect(await provider.connection.getAccountInfo(vaultPda(deal))).to.equal(null);
+    });
+
+    it("refuses a payout to a token account the provider doesn't own", async () => {
+      const { need, deal } = await openTokenDeal(null);
+      await program.methods
+        .submitDelivery("QmToken", "Token delivery")
+        .accounts({ global: globalPda, deal, provider: providerAccount.publicKey })
+        .signers([providerAccount])
+        .rpc();
+
+      await expectError(
+        program.methods
+          .confirmDelivery()
+          .accounts({
+            global: globalPda,
+            deal,
+            need,
+            client: creator.publicKey,
+            provider: providerAccount.publicKey,
+            treasury: authority.publicKey,
+            mint: mint.publicKey,
+            vault: vaultPda(deal),
+            providerTokenAccount: clientTokens,
+            tokenProgram: TOKEN_2022_PROGRAM_ID,
+          })
+          .signers([creator])
+          .rpc(),
+        "InvalidTokenAccount"
+      );
+    });
+  });
+
+  describe("escrow recipient validation", () => {
+    const attacker = anchor.web3.Keypair.generate();
+
+    let needPda: anchor.web3.PublicKey;
+    let otherNeedPda: anchor.web3.PublicKey;
+    let dealPda: anchor.web3.PublicKey;
+
+    const createNeed = async (title: string) => {
+      const global = await program.account.global.fetch(globalPda);
+      const pda = findPda("need", global.needCounter);
+      await program.methods
+        .createNeed(title, "Recipient validation fixture", new anchor.BN(50_000_000), null, false)
+        .accounts({ global: globalPda, need: pda, category: categoryPda, creator: creator.publicKey })
+        .signers([creator])
+        .rpc();
+      return pda;
+    };
+
+    const confirmDelivery = (need: anchor.web3.PublicKey, payee: anchor.web3.PublicKey) =>
+      program.methods
+        .confirmDelivery()
+        .accounts({
+          global: globalPda,
+          deal: dealPda,
+          need,
+          client: creator.publicKey,
+          provider: payee,
+          treasury: authority.publicKey,
+        })
+        .signers([creator])
+        .rpc();
+
+    const resolveDispute = (
+      resolution: object,
+      client: anchor.web3.PublicKey,
+      payee: anchor.web3.PublicKey
+    ) =>
+      program.methods
+        .resolveDispute(resolution as any)
+        .accounts({
+          global: globalPda,
+          deal: dealPda,
+          need: needPda,
+          resolver: authority.publicKey,
+          client,
+          provider: payee,
+          treasury: authority.publicKey,
+        })
+        .rpc();
+
+    before(async () => {
+      await provider.connection.requestAirdrop(attacker.publicKey, anchor.web3.LAMPORTS_PER_SOL);
+
+      needPda = await createNeed("Recipient check");
+      otherNeedPda = await createNeed("Unrelated need");
+      const need = await program.account.need.fetch(needPda);
+
+      const global = await program.account.global.fetch(globalPda);
+      const offerPda = findPda("offer", global.offerCounter);
+      await program.methods
+        .createOffer(need.id, new anchor.BN(20_000_000), "Fixture offer", null, [])
+        .accounts({ global: globalPda, need: needPda, offer: offerPda, provider: providerAccount.publicKey })
+        .signers([providerAccount])
+        .rpc();
+
+      dealPda = findPda("deal", global.dealCounter);
+      await program.methods
+        .acceptOffer(new anchor.BN(20_000_000))
+        .accounts({
+          global: globalPda,
+          need: needPda,
+          offer: offerPda,
+          category: categoryPda,
+          deal: dealPda,
+          client: creator.publicKey,
+        })
+        .signers([creator])
+        .rpc();
+
+      await program.methods
+        .submitDelivery("QmFixture", "Fixture delivery")
+        .accounts({ global: globalPda, deal: dealPda, provider: providerAccount.publicKey })
+        .signers([providerAccount])
+        .rpc();
+    });
+
+    it("rejects confirm_delivery paying an arbitrary account", async () => {
+      await expectError(confirmDelivery(needPda, attacker.publicKey), "InvalidRecipient");
+    });
+
+    it("rejects confirm_delivery with a need other than the deal's", async () => {
+      await expectError(confirmDelivery(otherNeedPda, providerAccount.publicKey), "DealNeedMismatch");
+    });
+
+    it("rejects resolve_dispute redirecting the refund or payout", async () => {
+      await program.methods
+        .raiseDispute("Delivery does not match the brief")
+        .accounts({ global: globalPda, deal: dealPda, caller: creator.publicKey })
+        .signers([creator])
+        .rpc();
+
+      await expectError(
+        resolveDispute({ refundClient: {} }, attacker.publicKey, providerAccount.publicKey),
+        "InvalidRecipient"
+      );
+      await expectError(
+        resolveDispute({ payProvider: {} }, creator.publicKey, attacker.publicKey),
+        "InvalidRecipient"
+      );
+    });
+
+    it("refunds the deal's own client", async () => {
+      const attackerBalanceBefore = await provider.connection.getBalance(attacker.publicKey);
+      const clientBalanceBefore = await provider.connection.getBalance(creator.publicKey);
+
+      await resolveDispute({ refundClient: {} }, creator.publicKey, providerAccount.publicKey);
+
+      const dealAccount = await program.account.deal.fetch(dealPda);
+      expect(dealAccount.status).to.deep.equal({ cancelled: {} });
+      expect(dealAccount.releasedLamports.toNumber()).to.equal(20_000_000);
+      expect(await provider.connection.getBalance(creator.publicKey)).to.equal(clientBalanceBefore + 20_000_000);
+      expect(await provider.connection.getBalance(attacker.publicKey)).to.equal(attackerBalanceBefore);
+    });
+  });
+
+  describe("deal timeouts", () => {
+    it("lets anyone refund an undelivered deal once its deadline passes", async () => {
+      const { need, deal } = await openDeal("Deadline fixture", 8_000_000, [], 1);
+
+      const refund = () =>
+        program.methods
+          .refundExpiredDeal()
+          .accounts({ global: globalPda, deal, need, client: creator.publicKey, caller: authority.publicKey })
+          .rpc();
+
+      await expectError(refund(), "DeliveryDeadlineNotReached");
+      await sleep(3_000);
+
+      const clientBalanceBefore = await provider.connection.getBalance(creator.publicKey);
+      await refund();
+
+      const dealAccount = await program.account.deal.fetch(deal);
+      expect(dealAccount.status).to.deep.equal({ cancelled: {} });
+      expect(dealAccount.releasedLamports.toNumber()).to.equal(8_000_000);
+      expect((await program.account.need.fetch(need)).status).to.deep.equal({ cancelled: {} });
+      expect(await provider.connection.getBalance(creator.publicKey)).to.equal(clientBalanceBefore + 8_000_000);
+    });
+
+    it("holds a delivered deal until the review window closes", async () => {
+      const { need, deal } = await openDeal("Review fixture", 8_000_000);
+      await program.methods
+        .submitDelivery("QmReview", "Awaiting review")
+        .accounts({ global: globalPda, deal, provider: providerAccount.publicKey })
+        .signers([providerAccount])
+        .rpc();
+
+      await expectError(
+        program.methods
+          .releaseExpiredDeal()
+          .accounts({
+            global: globalPda,
+            deal,
+            need,
+            provider: providerAccount.publicKey,
+            treasury: authority.publicKey,
+            caller: authority.publicKey,
+          })
+          .rpc(),
+        "ReviewWindowOpen"
+      );
+    });
+  });
+
+  describe("milestones", () => {
+    const deliverAndConfirm = async (need: anchor.web3.PublicKey, deal: anchor.web3.PublicKey) => {
+      await program.methods
+        .submitDelivery("QmMilestone", "Milestone delivery")
+        .accounts({ global: globalPda, deal, provider: providerAccount.publicKey })
+        .signers([providerAccount])
+        .rpc();
+      await program.methods
+        .confirmDelivery()
+        .accounts({
+          global: globalPda,
+          deal,
+          need,
+          client: creator.publicKey,
+          provider: providerAccount.publicKey,
+          treasury: authority.publicKey,
+        })
+        .signers([creator])
+        .rpc();
+    };
+
+    it("rejects milestones that don't add up to the price", async () => {
+      await expectError(openDeal("Short milestones", 10_000_000, [4_000_000, 5_000_000]), "InvalidMilestones");
+      await expectError(openDeal("Zero milestone", 10_000_000, [10_000_000, 0]), "InvalidMilestones");
+    });
+
+    it("releases one milestone per confirmation", async () => {
+      const { need, deal } = await openDeal("Milestone fixture", 10_000_000, [4_000_000, 6_000_000]);
+
+      const providerBefore = await provider.connection.getBalance(providerAccount.publicKey);
+      await deliverAndConfirm(need, deal);
+
+      let dealAccount = await program.account.deal.fetch(deal);
+      expect(dealAccount.status).to.deep.equal({ inProgress: {} });
+      expect(dealAccount.currentMilestone).to.equal(1);
+      expect(dealAccount.releasedLamports.toNumber()).to.equal(4_000_000);
+      expect((await program.account.need.fetch(need)).status).to.deep.equal({ inProgress: {} });
+      expect(await provider.connection.getBalance(providerAccount.publicKey)).to.equal(providerBefore + 4_000_000);
+
+      await deliverAndConfirm(need, deal);
+
+      dealAccount = await program.account.deal.fetch(deal);
+      expect(dealAccount.status).to.deep.equal({ completed: {} });
+      expect(dealAccount.releasedLamports.toNumber()).to.equal(10_000_000);
+      expect((await program.account.need.fetch(need)).status).to.deep.equal({ completed: {} });
+      expect(await provider.connection.getBalance(providerAccount.publicKey)).to.equal(providerBefore + 10_000_000);
+    });
+  });
+
+  describe("reputation", () => {
+    const reputationPda = (wallet: anchor.web3.PublicKey) =>
+      anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("reputation"), globalPda.toBuffer(), wallet.toBuffer()],
+        program.programId
+      )[0];
+
+    const rateDeal = (deal: anchor.web3.PublicKey, rater: anchor.web3.Keypair, ratee: anchor.web3.PublicKey, rating: number) =>
+      program.methods
+        .rateDeal(rating)
+        .accounts({ global: globalPda, deal, ratee, rater: rater.publicKey })
+        .signers([rater])
+        .rpc();
+
+    it("records completed deals and one rating per side", async () => {
+      const { need, deal } = await openDeal("Reputation fixture", 6_000_000);
+      await expectError(rateDeal(deal, creator, providerAccount.publicKey, 5), "DealNotFinished");
+
+      const before = await program.account.reputation.fetch(reputationPda(providerAccount.publicKey));
+      await program.methods
+        .submitDelivery("QmRated", "Rated delivery")
+        .accounts({ global: globalPda, deal, provider: providerAccount.publicKey })
+        .signers([providerAccount])
+        .rpc();
+      await program.methods
+        .confirmDelivery()
+        .accounts({
+          global: globalPda,
+          deal,
+          need,
+          client: creator.publicKey,
+          provider: providerAccount.publicKey,
+          treasury: authority.publicKey,
+        })
+        .signers([creator])
+        .rpc();
+
+      let reputation = await program.account.reputation.fetch(reputationPda(providerAccount.publicKey));
+      expect(reputation.completedDeals.toNumber()).to.equal(before.completedDeals.toNumber() + 1);
+      expect(reputation.volumeLamports.toNumber()).to.equal(before.volumeLamports.toNumber() + 6_000_000);
+
+      await expectError(rateDeal(deal, creator, providerAccount.publicKey, 0), "InvalidRating");
+      await expectError(rateDeal(deal, creator, providerAccount.publicKey, 6), "InvalidRating");
+      await expectError(rateDeal(deal, creator, creator.publicKey, 5), "NotDealParticipant");
+
+      await rateDeal(deal, creator, providerAccount.publicKey, 4);
+      await expectError(rateDeal(deal, creator, providerAccount.publicKey, 5), "AlreadyRated");
+      // The other side rates independently
+      await rateDeal(deal, providerAccount, creator.publicKey, 5);
+
+      reputation = await program.account.reputation.fetch(reputationPda(providerAccount.publicKey));
+      expect(reputation.ratingSum.toNumber()).to.equal(before.ratingSum.toNumber() + 4);
+      expect(reputation.ratingCount.toNumber()).to.equal(before.ratingCount.toNumber() + 1);
+    });
+  });
+
+  describe("account closing", () => {
+    const closeNeed = (need: anchor.web3.PublicKey, signer = creator) =>
+      program.methods
+        .closeNeed(false)
+        .accounts({ global: globalPda, need, creator: signer.publicKey })
+        .signers([signer])
+        .rpc();
+
+    const closeOffer = (offer: anchor.web3.PublicKey) =>
+      program.methods
+        .closeOffer(false)
+        .accounts({ global: globalPda, offer, provider: providerAccount.publicKey })
+        .signers([providerAccount])
+        .rpc();
+
+    const closeDeal = (deal: anchor.web3.PublicKey) =>
+      program.methods
+        .closeDeal(true)
+        .accounts({ global: globalPda, deal, client: creator.publicKey })
+        .signers([creator])
+        .rpc();
+
+    it("refuses to close accounts that are still live", async () => {
+      const { need, deal } = await openDeal("Live fixture", 3_000_000);
+      await expectError(closeNeed(need), "NotClosable");
+      await expectError(closeDeal(deal), "NotClosable");
+
+      const global = await program.account.global.fetch(globalPda);
+      const openNeed = findPda("need", global.needCounter);
+      const offer = findPda("offer", global.offerCounter);
+      await program.methods
+        .createNeed("Open fixture", "desc", new anchor.BN(1_000_000), null, false)
+        .accounts({ global: globalPda, need: openNeed, category: categoryPda, creator: creator.publicKey })
+        .signers([creator])
+        .rpc();
+      await program.methods
+        .createOffer(global.needCounter, new anchor.BN(1_000_000), "Pending offer", null, [])
+        .accounts({ global: globalPda, need: openNeed, offer, provider: providerAccount.publicKey })
+        .signers([providerAccount])
+        .rpc();
+      await expectError(closeNeed(openNeed), "NotClosable");
+      await expectError(closeOffer(offer), "NotClosable");
+    });
+
+    it("returns the rent of a finished deal's accounts", async () => {
+      const { need, offer, deal } = await openDeal("Closable fixture", 3_000_000);
+      await program.methods
+        .submitDelivery("QmClose", "Closable delivery")
+        .accounts({ global: globalPda, deal, provider: providerAccount.publicKey })
+        .signers([providerAccount])
+        .rpc();
+      await program.methods
+        .confirmDelivery()
+        .accounts({
+          global: globalPda,
+          deal,
+          need,
+          client: creator.publicKey,
+          provider: providerAccount.publicKey,
+          treasury: authority.publicKey,
+        })
+        .signers([creator])
+        .rpc();
+
+      await expectError(closeNeed(need, providerAccount), "NotNeedCreator");
+
+      const dealRent = await provider.connection.getBalance(deal);
+      const clientBalanceBefore = await provider.connection.getBalance(creator.publicKey);
+      await closeDeal(deal);
+      expect(await provider.connection.getBalance(creator.publicKey)).to.equal(clientBalanceBefore + dealRent);
+
+      await closeNeed(need);
+      await closeOffer(offer);
+      for (const account of [need, offer, deal]) {
+        expect(await provider.connection.getAccountInfo(account)).to.equal(null);
+      }
+    });
+  });
+
+  describe("offer acceptance", () => {
+    const rival = anchor.web3.Keypair.generate();
+
+    const createNeed = async () => {
+      const global = await program.account.global.fetch(globalPda);
+      const need = findPda("need", global.needCounter);
+      await program.methods
+        .createNeed("Competitive need", "desc", new anchor.BN(5_000_000), null, false)
+        .accounts({ global: globalPda, need, category: categoryPda, creator: creator.publicKey })
+        .signers([creator])
+        .rpc();
+      return { needId: global.needCounter, need };
+    };
+
+    const createOffer = async (needId: anchor.BN, need: anchor.web3.PublicKey, from: anchor.web3.Keypair) => {
+      const global = await program.account.global.fetch(globalPda);
+      const offer = findPda("offer", global.offerCounter);
+      await program.methods
+        .createOffer(needId, new anchor.BN(4_000_000), "Competing offer", null, [])
+        .accounts({ global: globalPda, need, offer, provider: from.publicKey })
+        .signers([from])
+        .rpc();
+      return offer;
+    };
+
+    const rejectOffer = (need: anchor.web3.PublicKey, offer: anchor.web3.PublicKey, signer: anchor.web3.Keypair) =>
+      program.methods
+        .rejectOffer()
+        .accounts({ global: globalPda, need, offer, creator: signer.publicKey })
+        .signers([signer])
+        .rpc();
+
+    const acceptOffer = async (
+      need: anchor.web3.PublicKey,
+      offer: anchor.web3.PublicKey,
+      competing: { pubkey: anchor.web3.PublicKey; isWritable: boolean }[] = [],
+      maxPrice = 4_000_000,
+      client = creator
+    ) => {
+      const global = await program.account.global.fetch(globalPda);
+      return program.methods
+        .acceptOffer(new anchor.BN(maxPrice))
+        .accounts({ global: globalPda, need, offer, category: categoryPda, deal: findPda("deal", global.dealCounter), client: client.publicKey })
+        .remainingAccounts(competing.map(({ pubkey, isWritable }) => ({ pubkey, isWritable, isSigner: false })))
+        .signers([client])
+        .rpc();
+    };
+
+    before(async () => {
+      await provider.connection.confirmTransaction(
+        await provider.connection.requestAirdrop(rival.publicKey, anchor.web3.LAMPORTS_PER_SOL)
+      );
+    });
+
+    it("lets only the need's creator reject a pending offer", async () => {
+      const { needId, need } = await createNeed();
+      const offer = await createOffer(needId, need, providerAccount);
+
+      await expectError(rejectOffer(need, offer, providerAccount), "NotNeedCreator");
+      await rejectOffer(need, offer, creator);
+      expect((await program.account.offer.fetch(offer)).status).to.deep.equal({ rejected: {} });
+      await expectError(rejectOffer(need, offer, creator), "OfferNotPending");
+    });
+
+    it("rejects the competing offers passed alongside an acceptance", async () => {
+      const { needId, need } = await createNeed();
+      const chosen = await createOffer(needId, need, providerAccount);
+      const competing = await createOffer(needId, need, rival);
+
+      const other = await createNeed();
+      const unrelated = await createOffer(other.needId, other.need, rival);
+
+      await expectError(acceptOffer(need, chosen, [{ pubkey: unrelated, isWritable: true }]), "InvalidCompetingOffer");
+      await expectError(acceptOffer(need, chosen, [{ pubkey: competing, isWritable: false }]), "InvalidCompetingOffer");
+      await expectError(acceptOffer(need, chosen, [{ pubkey: chosen, isWritable: true }]), "InvalidCompetingOffer");
+
+      await acceptOffer(need, chosen, [{ pubkey: competing, isWritable: true }]);
+      expect((await program.account.offer.fetch(chosen)).status).to.deep.equal({ accepted: {} });
+      expect((await program.account.offer.fetch(competing)).status).to.deep.equal({ rejected: {} });
+      expect((await program.account.offer.fetch(unrelated)).status).to.deep.equal({ pending: {} });
+    });
+
+    it("skips competing offers withdrawn before the acceptance lands", async () => {
+      const { needId, need } = await createNeed();
+      const chosen = await createOffer(needId, need, providerAccount);
+      const cancelled = await createOffer(needId, need, rival);
+      const closed = await createOffer(needId, need, rival);
+      const pending = await createOffer(needId, need, rival);
+
+      for (const offer of [cancelled, closed]) {
+        await program.methods
+          .cancelOffer()
+          .accounts({ global: globalPda, offer, provider: rival.publicKey })
+          .signers([rival])
+          .rpc();
+      }
+      await program.methods
+        .closeOffer(false)
+        .accounts({ global: globalPda, offer: closed, provider: rival.publicKey })
+        .signers([rival])
+        .rpc();
+
+      await acceptOffer(
+        need,
+        chosen,
+        [cancelled, closed, pending].map((pubkey) => ({ pubkey, isWritable: true }))
+      );
+      expect((await program.account.offer.fetch(cancelled)).status).to.deep.equal({ cancelled: {} });
+      expect((await program.account.offer.fetch(pending)).status).to.deep.equal({ rejected: {} });
+    });
+
+    it("binds the accepted offer to its need, creator and expected price", async () => {
+      const { needId, need } = await createNeed();
+      const offer = await createOffer(needId, need, providerAccount);
+      const other = await createNeed();
+
+      await expectError(acceptOffer(other.need, offer), "OfferNeedMismatch");
+      await expectError(acceptOffer(need, offer, [], 3_999_999), "PriceAboveExpected");
+      await expectError(acceptOffer(need, offer, [], 4_000_000, rival), "NotNeedCreator");
+
+      const deal = findPda("deal", (await program.account.global.fetch(globalPda)).dealCounter);
+      await acceptOffer(need, offer);
+      const dealAccount = await program.account.deal.fetch(deal);
+      expect(dealAccount.needId.toNumber()).to.equal(needId.toNumber());
+      expect(dealAccount.amountLamports.toNumber()).to.equal(4_000_000);
+    });
+  });
+
+  describe("editing needs and offers", () => {
+    let needId: anchor.BN;
+    let need: anchor.web3.PublicKey;
+    let offer: anchor.web3.PublicKey;
+
+    const hoursFromNow = (hours: number) => new anchor.BN(Math.floor(Date.now() / 1000) + hours * 3600);
+
+    const updateNeed = (deadline: anchor.BN | null, signer = creator) =>
+      program.methods
+        .updateNeed("Edited need", "Edited description", new anchor.BN(2_000_000), deadline)
+        .accounts({ global: globalPda, need, category: categoryPda, creator: signer.publicKey })
+        .signers([signer])
+        .rpc();
+
+    const updateOffer = () =>
+      program.methods
+        .updateOffer(new anchor.BN(1_500_000), "Edited offer", null, [])
+        .accounts({ global: globalPda, need, offer, provider: providerAccount.publicKey })
+        .signers([providerAccount])
+        .rpc();
+
+    before(async () => {
+      const global = await program.account.global.fetch(globalPda);
+      needId = global.needCounter;
+      need = findPda("need", needId);
+      offer = findPda("offer", global.offerCounter);
+      await program.methods
+        .createNeed("Editable need", "desc", new anchor.BN(1_000_000), null, false)
+        .accounts({ global: globalPda, need, category: categoryPda, creator: creator.publicKey })
+        .signers([creator])
+        .rpc();
+      await program.methods
+        .createOffer(needId, new anchor.BN(1_000_000), "Editable offer", null, [])
+        .accounts({ global: globalPda, need, offer, provider: providerAccount.publicKey })
+        .signers([providerAccount])
+        .rpc();
+    });
+
+    it("lets the creator edit an open need with a future deadline", async () => {
+      await expectError(updateNeed(null, providerAccount), "NotNeedCreator");
+      await expectError(updateNeed(hoursFromNow(-1)), "InvalidDeadline");
+
+      const deadline = hoursFromNow(24);
+      await updateNeed(deadline);
+      const account = await program.account.need.fetch(need);
+      expect(account.title).to.equal("Edited need");
+      expect(account.budgetLamports.toNumber()).to.equal(2_000_000);
+      expect(account.deadline.toNumber()).to.equal(deadline.toNumber());
+      expect(account.revision).to.equal(1);
+    });
+
+    it("lets the provider edit a pending offer while the need is open", async () => {
+      await updateOffer();
+      const account = await program.account.offer.fetch(offer);
+      expect(account.priceLamports.toNumber()).to.equal(1_500_000);
+      expect(account.message).to.equal("Edited offer");
+      expect(account.revision).to.equal(1);
+    });
+
+    it("freezes both once the need is cancelled", async () => {
+      await program.methods
+        .cancelNeed()
+        .accounts({ global: globalPda, need, creator: creator.publicKey })
+        .signers([creator])
+        .rpc();
+
+      await expectError(updateNeed(null), "NeedNotOpen");
+      await expectError(updateOffer(), "NeedNotOpen");
+    });
+  });
+
+  describe("string length validation", () => {
+    // Limits are enforced on UTF-8 byte length, which is what the account stores
+    const emoji = "\u{1F980}"; // 4 bytes, 1 character (2 UTF-16 units)
+
+    const createNeed = async (title: string, description = "desc") => {
+      const global = await program.account.global.fetch(globalPda);
+      const pda = findPda("need", global.needCounter);
+      await program.methods
+        .createNeed(title, description, new anchor.BN(1_000_000), null, false)
+        .accounts({ global: globalPda, need: pda, category: categoryPda, creator: creator.publicKey })
+        .signers([creator])
+        .rpc();
+      return pda;
+    };
+
+    it("accepts a title of exactly 64 ASCII bytes", async () => {
+      const needPda = await createNeed("a".repeat(64));
+      const need = await program.account.need.fetch(needPda);
+      expect(need.title).to.have.lengthOf(64);
+    });
+
+    it("rejects a 65-byte title", async () => {
+      await expectError(createNeed("a".repeat(65)), "TitleTooLong");
+    });
+
+    it("counts bytes, not characters, for multi-byte titles", async () => {
+      // 16 emoji = 64 bytes fits; 17 emoji = 68 bytes does not, though it is only 17 characters
+      expect(Buffer.byteLength(emoji.repeat(16))).to.equal(64);
+      await createNeed(emoji.repeat(16));
+      await expectError(createNeed(emoji.repeat(17)), "TitleTooLong");
+    });
+
+    it("rejects an oversized description", async () => {
+      await expectError(createNeed("title", "d".repeat(257)), "DescriptionTooLong");
+    });
+
+    it("rejects an oversized offer message", async () => {
+      const needPda = await createNeed("Message limits");
+      const need = await program.account.need.fetch(needPda);
+      const global = await program.account.global.fetch(globalPda);
+      const offerPda = findPda("offer", global.offerCounter);
+
+      await expectError(
+        program.methods
+          .createOffer(need.id, new anchor.BN(500_000), emoji.repeat(65), null, [])
+          .accounts({ global: globalPda, need: needPda, offer: offerPda, provider: providerAccount.publicKey })
+          .signers([providerAccount])
+          .rpc(),
+        "MessageTooLong"
+      );
+    });
+  });
+
+  describe("category registry", () => {
+    const outsider = anchor.web3.Keypair.generate();
+
+    const createCategory = async (name: string, minBudget: number, signer?: anchor.web3.Keypair) => {
+      const global = await program.account.global.fetch(globalPda);
+      const pda = findPda("category", global.categoryCounter);
+      const builder = program.methods
+        .createCategory(name, 250, new anchor.BN(minBudget))
+        .accounts({
+          global: globalPda,
+          category: pda,
+          categoryName: categoryNamePda(name),
+          authority: (signer ?? authority).publicKey,
+        });
+      await (signer ? builder.signers([signer]) : builder).rpc();
+      return pda;
+    };
+
+    const createNeed = async (category: anchor.web3.PublicKey, budget: number) => {
+      const global = await program.account.global.fetch(globalPda);
+      await program.methods
+        .createNeed("Category fixture", "desc", new anchor.BN(budget), null, false)
+        .accounts({ global: globalPda, need: findPda("need", global.needCounter), category, creator: creator.publicKey })
+        .signers([creator])
+        .rpc();
+    };
+
+    before(async () => {
+      await provider.connection.requestAirdrop(outsider.publicKey, anchor.web3.LAMPORTS_PER_SOL);
+    });
+
+    it("only lets the authority create categories", async () => {
+      await expectError(createCategory("spam", 0, outsider), "NotAuthority");
+    });
+
+    it("rejects names that are not lowercase slugs", async () => {
+      await expectError(createCategory("Audit", 0), "InvalidCategoryName");
+      await expectError(createCategory("security audit", 0), "InvalidCategoryName");
+      await expectError(createCategory("", 0), "InvalidCategoryName");
+      await expectError(createCategory("a".repeat(33), 0), "CategoryTooLong");
+    });
+
+    it("rejects a name that is already taken", async () => {
+      await createCategory("research", 0);
+      await expectError(createCategory("research", 0), "CategoryNameTaken");
+    });
+
+    it("enforces the category minimum budget for SOL needs", async () => {
+      const category = await createCategory("audit", 10_000_000);
+      await expectError(createNeed(category, 9_999_999), "BelowCategoryMinimum");
+      await createNeed(category, 10_000_000);
+
+      const account = await program.account.category.fetch(category);
+      expect(account.feeBps).to.equal(250);
+    });
+
+    it("refuses new needs in a deactivated category", async () => {
+      const category = await createCategory("design", 0);
+      await program.methods
+        .updateCategory(false, null, new anchor.BN(0))
+        .accounts({ global: globalPda, category, authority: authority.publicKey })
+        .rpc();
+
+      await expectError(createNeed(category, 1_000_000), "CategoryInactive");
+    });
+  });
+
+  describe("protocol fee", () => {
+    const treasury = anchor.web3.Keypair.generate();
+
+    const setFeeConfig = (feeBps: number, feeTreasury: anchor.web3.PublicKey, signer?: anchor.web3.Keypair) => {
+      const builder = program.methods
+        .setFeeConfig(feeBps, feeTreasury)
+        .accounts({ global: globalPda, treasury: feeTreasury, authority: (signer ?? authority).publicKey });
+      return (signer ? builder.signers([signer]) : builder).rpc();
+    };
+
+    const deliverAndConfirm = async (need: anchor.web3.PublicKey, deal: anchor.web3.PublicKey, payTo: anchor.web3.PublicKey) => {
+      await program.methods
+        .submitDelivery("QmFee", "Fee fixture")
+        .accounts({ global: globalPda, deal, provider: providerAccount.publicKey })
+        .signers([providerAccount])
+        .rpc();
+      return program.methods
+        .confirmDelivery()
+        .accounts({
+          global: globalPda,
+          deal,
+          need,
+          client: creator.publicKey,
+          provider: providerAccount.publicKey,
+          treasury: payTo,
+        })
+        .signers([creator])
+        .rpc();
+    };
+
+    before(async () => {
+      await provider.connection.confirmTransaction(
+        await provider.connection.requestAirdrop(treasury.publicKey, anchor.web3.LAMPORTS_PER_SOL)
+      );
+    });
+
+    after(async () => {
+      await setFeeConfig(0, authority.publicKey);
+    });
+
+    it("only lets the authority set a fee within the cap", async () => {
+      await expectError(setFeeConfig(500, creator.publicKey, creator), "NotAuthority");
+      await expectError(setFeeConfig(1_001, treasury.publicKey), "FeeTooHigh");
+    });
+
+    it("refuses a treasury that isn't rent-exempt", async () => {
+      // Crediting a small fee to an empty account would fail every payout
+      const empty = anchor.web3.Keypair.generate();
+      await expectError(setFeeConfig(500, empty.publicKey), "TreasuryNotRentExempt");
+      await expectError(
+        program.methods
+          .setFeeConfig(500, treasury.publicKey)
+          .accounts({ global: globalPda, treasury: empty.publicKey, authority: authority.publicKey })
+          .rpc(),
+        "InvalidTreasury"
+      );
+    });
+
+    it("takes the global fee from the provider's payout into the treasury", async () => {
+      await setFeeConfig(500, treasury.publicKey);
+      const { need, deal } = await openDeal("Fee fixture", 20_000_000);
+      expect((await program.account.deal.fetch(deal)).feeBps).to.equal(500);
+
+      await expectError(deliverAndConfirm(need, deal, authority.publicKey), "InvalidTreasury");
+
+      const providerBefore = await provider.connection.getBalance(providerAccount.publicKey);
+      const treasuryBefore = await provider.connection.getBalance(treasury.publicKey);
+      await program.methods
+        .confirmDelivery()
+        .accounts({
+          global: globalPda,
+          deal,
+          need,
+          client: creator.publicKey,
+          provider: providerAccount.publicKey,
+          treasury: treasury.publicKey,
+        })
+        .signers([creator])
+        .rpc();
+
+      expect(await provider.connection.getBalance(providerAccount.publicKey)).to.equal(providerBefore + 19_000_000);
+      expect(await provider.connection.getBalance(treasury.publicKey)).to.equal(treasuryBefore + 1_000_000);
+    });
+
+    it("lets a category's fee override the global one", async () => {
+      await setFeeConfig(500, treasury.publicKey);
+      await program.methods
+        .updateCategory(true, 250, new anchor.BN(0))
+        .accounts({ global: globalPda, category: categoryPda, authority: authority.publicKey })
+        .rpc();
+
+      try {
+        const { need, deal } = await openDeal("Category fee fixture", 20_000_000);
+        expect((await program.account.deal.fetch(deal)).feeBps).to.equal(250);
+
+        const providerBefore = await provider.connection.getBalance(providerAccount.publicKey);
+        const treasuryBefore = await provider.connection.getBalance(treasury.publicKey);
+        await deliverAndConfirm(need, deal, treasury.publicKey);
+
+        expect(await provider.connection.getBalance(providerAccount.publicKey)).to.equal(providerBefore + 19_500_000);
+        expect(await provider.connection.getBalance(treasury.publicKey)).to.equal(treasuryBefore + 500_000);
+      } finally {
+        await program.methods
+          .updateCategory(true, null, new anchor.BN(0))
+          .accounts({ global: globalPda, category: categoryPda, authority: authority.publicKey })
+          .rpc();
+      }
+    });
+  });
+
+  describe("authority handover", () => {
+    const proposeAuthority = (newAuthority: anchor.web3.PublicKey, signer?: anchor.web3.Keypair) => {
+      const builder = program.methods
+        .proposeAuthority(newAuthority)
+        .accounts({ global: globalPda, authority: (signer ?? authority).publicKey });
+      return (signer ? builder.signers([signer]) : builder).rpc();
+    };
+
+    const acceptAuthority = (signer?: anchor.web3.Keypair) => {
+      const builder = program.methods
+        .acceptAuthority()
+        .accounts({ global: globalPda, pendingAuthority: (signer ?? authority).publicKey });
+      return (signer ? builder.signers([signer]) : builder).rpc();
+    };
+
+    it("hands the authority over only once the proposed key accepts", async () => {
+      await expectError(acceptAuthority(creator), "NoPendingAuthority");
+      await expectError(proposeAuthority(creator.publicKey, creator), "NotAuthority");
+
+      await proposeAuthority(creator.publicKey);
+      await expectError(acceptAuthority(providerAccount), "NotPendingAuthority");
+      let global = await program.account.global.fetch(globalPda);
+      expect(global.authority.toString()).to.equal(authority.publicKey.toString());
+      expect(global.pendingAuthority.toString()).to.equal(creator.publicKey.toString());
+
+      await acceptAuthority(creator);
+      global = await program.account.global.fetch(globalPda);
+      expect(global.authority.toString()).to.equal(creator.publicKey.toString());
+      expect(global.pendingAuthority).to.equal(null);
+
+      // The previous authority is locked out
+      await expectError(proposeAuthority(authority.publicKey), "NotAuthority");
+
+      // Hand it back for the rest of the suite
+      await proposeAuthority(authority.publicKey, creator);
+      await acceptAuthority();
+      global = await program.account.global.fetch(globalPda);
+      expect(global.authority.toString()).to.equal(authority.publicKey.toString());
+    });
+
+    it("leaves a renounced global without an authority", async () => {
+      const renouncedId = new anchor.BN(3);
+      const [renouncedGlobal] = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("global"), renouncedId.toArrayLike(Buffer, "le", 8)],
+        program.programId
+      );
+      await program.methods
+        .initialize(renouncedId)
+        .accounts({ global: renouncedGlobal, authority: authority.publicKey })
+        .rpc();
+
+      await expectError(
+        program.methods
+          .renounceAuthority()
+          .accounts({ global: renouncedGlobal, authority: creator.publicKey })
+          .signers([creator])
+          .rpc(),
+        "NotAuthority"
+      );
+      await program.methods
+        .renounceAuthority()
+        .accounts({ global: renouncedGlobal, authority: authority.publicKey })
+        .rpc();
+
+      const global = await program.account.global.fetch(renouncedGlobal);
+      expect(global.authority.toString()).to.equal(anchor.web3.PublicKey.default.toString());
+      await expectError(
+        program.methods
+          .setPaused(true, true)
+          .accounts({ global: renouncedGlobal, authority: authority.publicKey })
+          .rpc(),
+        "NotAuthority"
+      );
+    });
+  });
+
+  describe("emergency pause", () => {
+    const setPaused = (marketplace: boolean, barter: boolean, signer?: anchor.web3.Keypair) => {
+      const builder = program.methods
+        .setPaused(marketplace, barter)
+        .accounts({ global: globalPda, authority: (signer ?? authority).publicKey });
+      return (signer ? builder.signers([signer]) : builder).rpc();
+    };
+
+    const createNeed = async () => {
+      const global = await program.account.global.fetch(globalPda);
+      return program.methods
+        .createNeed("Paused need", "desc", new anchor.BN(1_000_000), null, false)
+        .accounts({ global: globalPda, need: findPda("need", global.needCounter), category: categoryPda, creator: creator.publicKey })
+        .signers([creator])
+        .rpc();
+    };
+
+    const createBarter = async () => {
+      const global = await program.account.global.fetch(globalPda);
+      return program.methods
+        .createBarter("Paused offer", "Paused want", null, new anchor.BN(0), null, null)
+        .accounts({ global: globalPda, barter: findPda("barter", global.barterCounter), initiator: creator.publicKey })
+        .signers([creator])
+        .rpc();
+    };
+
+    after(async () => {
+      await setPaused(false, false);
+    });
+
+    it("only lets the authority pause", async () => {
+      await expectError(setPaused(true, true, creator), "NotAuthority");
+    });
+
+    it("blocks new marketplace activity but lets open deals settle", async () => {
+      const { need, deal } = await openDeal("Settles while paused", 5_000_000);
+      await setPaused(true, false);
+
+      await expectError(createNeed(), "Paused");
+      // The barter side has its own switch
+      await createBarter();
+
+      await program.methods
+        .submitDelivery("QmPaused", "Delivered during the pause")
+        .accounts({ global: globalPda, deal, provider: providerAccount.publicKey })
+        .signers([providerAccount])
+        .rpc();
+      await program.methods
+        .confirmDelivery()
+        .accounts({
+          global: globalPda,
+          deal,
+          need,
+          client: creator.publicKey,
+          provider: providerAccount.publicKey,
+          treasury: authority.publicKey,
+        })
+        .signers([creator])
+        .rpc();
+      expect((await program.account.deal.fetch(deal)).status).to.deep.equal({ completed: {} });
+    });
+
+    it("blocks new barters under the barter switch", async () => {
+      await setPaused(false, true);
+      await expectError(createBarter(), "Paused");
+      await createNeed();
+    });
+  });
+
+  describe("global namespacing", () => {
+    const otherId = new anchor.BN(2);
+    let otherGlobal: anchor.web3.PublicKey;
+    let otherCategory: anchor.web3.PublicKey;
+
+    const pdaUnder = (global: anchor.web3.PublicKey, seed: string, id: anchor.BN) =>
+      anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from(seed), global.toBuffer(), id.toArrayLike(Buffer, "le", 8)],
+        program.programId
+      )[0];
+
+    const createNeed = (global: anchor.web3.PublicKey, need: anchor.web3.PublicKey) =>
+      program.methods
+        .createNeed("Namespaced need", "desc", new anchor.BN(1_000_000), null, false)
+        .accounts({ global, need, category: otherCategory, creator: creator.publicKey })
+        .signers([creator])
+        .rpc();
+
+    before(async () => {
+      [otherGlobal] = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("global"), otherId.toArrayLike(Buffer, "le", 8)],
+        program.programId
+      );
+      await program.methods
+        .initialize(otherId)
+        .accounts({ global: otherGlobal, authority: authority.publicKey })
+        .rpc();
+
+      otherCategory = pdaUnder(otherGlobal, "category", new anchor.BN(0));
+      await program.methods
+        .createCategory("development", null, new anchor.BN(0))
+        .accounts({
+          global: otherGlobal,
+          category: otherCategory,
+          categoryName: categoryNamePda("development", otherGlobal),
+          authority: authority.publicKey,
+        })
+        .rpc();
+    });
+
+    it("keeps separate counters and addresses per global", async () => {
+      const need = pdaUnder(otherGlobal, "need", new anchor.BN(0));
+      await createNeed(otherGlobal, need);
+
+      const account = await program.account.need.fetch(need);
+      expect(account.id.toNumber()).to.equal(0);
+      expect(need.toString()).to.not.equal(findPda("need", new anchor.BN(0)).toString());
+      expect((await program.account.global.fetch(otherGlobal)).needCounter.toNumber()).to.equal(1);
+    });
+
+    it("rejects a PDA derived under another global", async () => {
+      // Same counter value, but seeded with the first global's key
+      await expectError(createNeed(otherGlobal, findPda("need", new anchor.BN(1))), "ConstraintSeeds");
+    });
+  });
+
+  describe("barter collateral", () => {
+    const initiator = anchor.web3.Keypair.generate();
+    const counterpart = anchor.web3.Keypair.generate();
+    const stake = new anchor.BN(100_000_000);
+
+    const balance = (key: anchor.web3.PublicKey) => provider.connection.getBalance(key);
+
+    // Creates a staked barter, has the counterpart accept it and the initiator dispute it
+    const disputedBarter = async () => {
+      const global = await program.account.global.fetch(globalPda);
+      const barterPda = findPda("barter", global.barterCounter);
+
+      await program.methods
+        .createBarter("Logo design", "Landing page copy", counterpart.publicKey, stake, null, null)
+        .accounts({ global: globalPda, barter: barterPda, initiator: initiator.publicKey })
+        .signers([initiator])
+        .rpc();
+      await program.methods
+        .acceptBarter()
+        .accounts({ global: globalPda, barter: barterPda, caller: counterpart.publicKey })
+        .signers([counterpart])
+        .rpc();
+      await program.methods
+        .disputeBarter("Counterpart went silent")
+        .accounts({ global: globalPda, barter: barterPda, caller: initiator.publicKey })
+        .signers([initiator])
+        .rpc();
+
+      return barterPda;
+    };
+
+    const resolve = (barter: anchor.web3.PublicKey, resolution: object, signer?: anchor.web3.Keypair) => {
+      const builder = program.methods.resolveBarterDispute(resolution as any).accounts({
+        global: globalPda,
+        barter,
+        resolver: (signer ?? authority).publicKey,
+        initiator: initiator.publicKey,
+        counterpart: counterpart.publicKey,
+      });
+      return (signer ? builder.signers([signer]) : builder).rpc();
+    };
+
+    before(async () => {
+      for (const wallet of [initiator, counterpart]) {
+        const sig = await provider.connection.requestAirdrop(wallet.publicKey, 2 * anchor.web3.LAMPORTS_PER_SOL);
+        await provider.connection.confirmTransaction(sig);
+      }
+    });
+
+    it("holds both stakes on the barter account", async () => {
+      const barterPda = await disputedBarter();
+      const barter = await program.account.barter.fetch(barterPda);
+      const rent = await provider.connection.getMinimumBalanceForRentExemption(
+        (await provider.connection.getAccountInfo(barterPda))!.data.length
+      );
+
+      expect(barter.collateralLamports.eq(stake)).to.equal(true);
+      expect(await balance(barterPda)).to.equal(rent + 2 * stake.toNumber());
+    });
+
+    it("only lets the authority resolve", async () => {
+      const barterPda = await disputedBarter();
+      await expectError(resolve(barterPda, { cancel: {} }, counterpart), "NotBarterResolver");
+    });
+
+    it("slashes the at-fault side's stake to the other side", async () => {
+      const barterPda = await disputedBarter();
+      const before = await balance(initiator.publicKey);
+
+      await resolve(barterPda, { slash: { side: { b: {} } } });
+
+      const barter = await program.account.barter.fetch(barterPda);
+      expect(barter.status).to.deep.equal({ cancelled: {} });
+      expect((await balance(initiator.publicKey)) - before).to.equal(2 * stake.toNumber());
+
+      const [reputationPda] = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("reputation"), globalPda.toBuffer(), counterpart.publicKey.toBuffer()],
+        program.programId
+      );
+      const reputation = await program.account.reputation.fetch(reputationPda);
+      expect(reputation.disputesLost.toNumber()).to.be.greaterThan(0);
+    });
+
+    it("splits the pool by basis points", async () => {
+      const barterPda = await disputedBarter();
+      const initiatorBefore = await balance(initiator.publicKey);
+      const counterpartBefore = await balance(counterpart.publicKey);
+
+      await expectError(resolve(barterPda, { split: { initiatorBps: 10_001 } }), "InvalidSplit");
+      await resolve(barterPda, { split: { initiatorBps: 2_500 } });
+
+      expect((await balance(initiator.publicKey)) - initiatorBefore).to.equal(stake.toNumber() / 2);
+      expect((await balance(counterpart.publicKey)) - counterpartBefore).to.equal((stake.toNumber() * 3) / 2);
+    });
+
+    it("returns the initiator's stake when an open barter is cancelled", async () => {
+      const global = await program.account.global.fetch(globalPda);
+      const barterPda = findPda("barter", global.barterCounter);
+      await program.methods
+        .createBarter("Audit", "Audit", null, stake, null, null)
+        .accounts({ global: globalPda, barter: barterPda, initiator: initiator.publicKey })
+        .signers([initiator])
+        .rpc();
+
+      const before = await balance(barterPda);
+      await program.methods
+        .cancelBarter()
+        .accounts({ global: globalPda, barter: barterPda, initiator: initiator.publicKey })
+        .signers([initiator])
+        .rpc();
+
+      expect(before - (await balance(barterPda))).to.equal(stake.toNumber());
+    });
+
+    it("lets an assigned arbiter resolve", async () => {
+      const arbiter = anchor.web3.Keypair.generate();
+      const sig = await provider.connection.requestAirdrop(arbiter.publicKey, anchor.web3.LAMPORTS_PER_SOL);
+      await provider.connection.confirmTransaction(sig);
+      const barterPda = await disputedBarter();
+
+      await expectError(resolve(barterPda, { cancel: {} }, arbiter), "NotBarterResolver");
+      await program.methods
+        .assignBarterArbiter(arbiter.publicKey)
+        .accounts({ global: globalPda, barter: barterPda, authority: authority.publicKey })
+        .rpc();
+      await resolve(barterPda, { complete: {} }, arbiter);
+
+      const barter = await program.account.barter.fetch(barterPda);
+      expect(barter.status).to.deep.equal({ completed: {} });
+      expect(barter.sideAConfirmed && barter.sideBConfirmed).to.equal(true);
+    });
+
+    it("marks a delivered side fulfilled and resumes the barter", async () => {
+      const global = await program.account.global.fetch(globalPda);
+      const barterPda = findPda("barter", global.barterCounter);
+      await program.methods
+        .createBarter("Logo design", "Landing page copy", counterpart.publicKey, new anchor.BN(0), null, null)
+        .accounts({ global: globalPda, barter: barterPda, initiator: initiator.publicKey })
+        .signers([initiator])
+        .rpc();
+      await program.methods
+        .acceptBarter()
+        .accounts({ global: globalPda, barter: barterPda, caller: counterpart.publicKey })
+        .signers([counterpart])
+        .rpc();
+      await program.methods
+        .submitBarterDelivery("ipfs://logo", "QmLogo")
+        .accounts({ global: globalPda, barter: barterPda, caller: initiator.publicKey })
+        .signers([initiator])
+        .rpc();
+      await program.methods
+        .disputeBarter("Initiator's logo is fine, but they refuse to confirm it was delivered")
+        .accounts({ global: globalPda, barter: barterPda, caller: counterpart.publicKey })
+        .signers([counterpart])
+        .rpc();
+
+      await expectError(resolve(barterPda, { sideFulfilled: { side: { b: {} } } }), "DeliveryNotReady");
+      await resolve(barterPda, { sideFulfilled: { side: { a: {} } } });
+
+      const barter = await program.account.barter.fetch(barterPda);
+      expect(barter.status).to.deep.equal({ inProgress: {} });
+      expect(barter.sideAConfirmed).to.equal(true);
+      expect(barter.sideBConfirmed).to.equal(false);
+    });
+  });
+
+  describe("barter expiry", () => {
+    const initiator = anchor.web3.Keypair.generate();
+    const stake = new anchor.BN(50_000_000);
+
+    const createBarter = async (acceptBy: number | null, deliverBy: number | null = null) => {
+      const global = await program.account.global.fetch(globalPda);
+      const barterPda = findPda("barter", global.barterCounter);
+      await program.methods
+        .createBarter(
+          "Translation",
+          "Proofreading",
+          null,
+          stake,
+          acceptBy === null ? null : new anchor.BN(acceptBy),
+          deliverBy === null ? null : new anchor.BN(deliverBy)
+        )
+        .accounts({ global: globalPda, barter: barterPda, initiator: initiator.publicKey })
+        .signers([initiator])
+        .rpc();
+      return barterPda;
+    };
+
+    const expire = (barter: anchor.web3.PublicKey) =>
+      program.methods
+        .expireBarter()
+        .accounts({
+          global: globalPda,
+          barter,
+          initiator: initiator.publicKey,
+          counterpart: anchor.web3.SystemProgram.programId,
+          caller: authority.publicKey,
+        })
+        .rpc();
+
+    before(async () => {
+      const sig = await provider.connection.requestAirdrop(initiator.publicKey, anchor.web3.LAMPORTS_PER_SOL);
+      await provider.connection.confirmTransaction(sig);
+    });
+
+    it("rejects deadlines in the past or out of order", async () => {
+      const now = await chainTime();
+      await expectError(createBarter(now - 10), "InvalidBarterDeadline");
+      await expectError(createBarter(now + 100, now + 50), "InvalidBarterDeadline");
+    });
+
+    it("cannot expire a barter without a passed deadline", async () => {
+      const barterPda = await createBarter(null);
+      await expectError(expire(barterPda), "BarterNotExpired");
+    });
+
+    it("cannot be accepted once its delivery deadline has passed", async () => {
+      const counterpart = anchor.web3.Keypair.generate();
+      await provider.connection.confirmTransaction(
+        await provider.connection.requestAirdrop(counterpart.publicKey, anchor.web3.LAMPORTS_PER_SOL)
+      );
+      const barterPda = await createBarter(null, (await chainTime()) + 2);
+
+      await sleep(4_000);
+      await expectError(
+        program.methods
+          .acceptBarter()
+          .accounts({ global: globalPda, barter: barterPda, caller: counterpart.publicKey })
+          .signers([counterpart])
+          .rpc(),
+        "DeliveryDeadlinePassed"
+      );
+    });
+
+    it("lets anyone expire an unaccepted barter and refunds the stake", async () => {
+      const barterPda = await createBarter((await chainTime()) + 2);
+      await expectError(expire(barterPda), "BarterNotExpired");
+
+      await sleep(4_000);
+      const before = await provider.connection.getBalance(initiator.publicKey);
+      await expire(barterPda);
+
+      const barter = await program.account.barter.fetch(barterPda);
+      expect(barter.status).to.deep.equal({ expired: {} });
+      expect((await provider.connection.getBalance(initiator.publicKey)) - before).to.equal(stake.toNumber());
+
+      await program.methods
+        .closeBarter(false)
+        .accounts({ global: globalPda, barter: barterPda, initiator: initiator.publicKey })
+        .signers([initiator])
+        .rpc();
+    });
+  });
+
+  describe("barter rings", () => {
+    const [alice, bob, carol] = [0, 1, 2].map(() => anchor.web3.Keypair.generate());
+
+    // alice → bob → carol → alice
+    const terms = [
+      { wallet: alice.publicKey, gives: "Smart contract audit", gets: "Brand identity" },
+      { wallet: bob.publicKey, gives: "Frontend build", gets: "Smart contract audit" },
+      { wallet: carol.publicKey, gives: "Brand identity", gets: "Frontend build" },
+    ];
+
+    const createRing = async (participants: typeof terms, deliverBy?: number) => {
+      const ring = findPda("barter_ring", (await program.account.global.fetch(globalPda)).ringCounter);
+      await program.methods
+        .createBarterRing(participants, new anchor.BN(deliverBy ?? (await chainTime()) + 600))
+        .accounts({ global: globalPda, ring, initiator: alice.publicKey })
+        .signers([alice])
+        .rpc();
+      return ring;
+    };
+
+    const submitDelivery = (ring: anchor.web3.PublicKey, wallet: anchor.web3.Keypair, hash: string) =>
+      program.methods
+        .submitRingDelivery(hash)
+        .accounts({ global: globalPda, ring, caller: wallet.publicKey })
+        .signers([wallet])
+        .rpc();
+
+    const expireRing = (ring: anchor.web3.PublicKey) =>
+      program.methods.expireBarterRing().accounts({ global: globalPda, ring, caller: authority.publicKey }).rpc();
+
+    const closeRing = (ring: anchor.web3.PublicKey) =>
+      program.methods
+        .closeBarterRing()
+        .accounts({ global: globalPda, ring, initiator: alice.publicKey })
+        .signers([alice])
+        .rpc();
+
+    const asParticipant = (method: "joinBarterRing" | "confirmRingLink", ring: anchor.web3.PublicKey, caller: anchor.web3.Keypair) =>
+      program.methods[method]()
+        .accounts({ global: globalPda, ring, caller: caller.publicKey })
+        .signers([caller])
+        .rpc();
+
+    before(async () => {
+      for (const wallet of [alice, bob, carol]) {
+        const sig = await provider.connection.requestAirdrop(wallet.publicKey, anchor.web3.LAMPORTS_PER_SOL);
+        await provider.connection.confirmTransaction(sig);
+      }
+    });
+
+    it("validates ring membership", async () => {
+      await expectError(createRing(terms.slice(0, 2)), "InvalidRingSize");
+      await expectError(createRing([terms[1], terms[0], terms[2]]), "RingInitiatorNotFirst");
+      await expectError(createRing([terms[0], terms[1], terms[1]]), "DuplicateRingParticipant");
+      await expectError(createRing(terms, (await chainTime()) - 10), "InvalidBarterDeadline");
+    });
+
+    it("completes only once every link is confirmed", async () => {
+      const ring = await createRing(terms);
+
+      await asParticipant("joinBarterRing", ring, bob);
+      expect((await program.account.barterRing.fetch(ring)).status).to.deep.equal({ open: {} });
+      await expectError(asParticipant("joinBarterRing", ring, bob), "AlreadyJoinedRing");
+      await asParticipant("joinBarterRing", ring, carol);
+      expect((await program.account.barterRing.fetch(ring)).status).to.deep.equal({ inProgress: {} });
+
+      // bob receives alice's delivery, so bob can't confirm before alice delivers
+      await expectError(asParticipant("confirmRingLink", ring, bob), "DeliveryNotReady");
+
+      for (const [i, wallet] of [alice, bob, carol].entries()) {
+        await submitDelivery(ring, wallet, `QmRing${i}`);
+      }
+      // Resubmitting is fine until the receiver confirms
+      await submitDelivery(ring, alice, "QmRing0v2");
+
+      await asParticipant("confirmRingLink", ring, bob);
+      await expectError(submitDelivery(ring, alice, "QmRing0v3"), "DeliveryAlreadyConfirmed");
+      await asParticipant("confirmRingLink", ring, carol);
+      let account = await program.account.barterRing.fetch(ring);
+      expect(account.status).to.deep.equal({ inProgress: {} });
+      expect(account.participants.map((participant) => participant.confirmed)).to.deep.equal([true, true, false]);
+
+      await asParticipant("confirmRingLink", ring, alice);
+      account = await program.account.barterRing.fetch(ring);
+      expect(account.status).to.deep.equal({ completed: {} });
+      expect(account.participants[0].deliveryHash).to.equal("QmRing0v2");
+
+      await closeRing(ring);
+    });
+
+    it("lets anyone expire a ring that misses its deadline", async () => {
+      const deliverBy = (await chainTime()) + 6;
+      const ring = await createRing(terms, deliverBy);
+      await expectError(closeRing(ring), "NotClosable");
+      for (const wallet of [bob, carol]) {
+        await asParticipant("joinBarterRing", ring, wallet);
+      }
+      await submitDelivery(ring, alice, "QmLate");
+      await expectError(expireRing(ring), "BarterNotExpired");
+
+      while ((await chainTime()) <= deliverBy + 1) {
+        await sleep(1_000);
+      }
+      await expectError(submitDelivery(ring, bob, "QmLate"), "DeliveryDeadlinePassed");
+      await expireRing(ring);
+      expect((await program.account.barterRing.fetch(ring)).status).to.deep.equal({ expired: {} });
+      await expectError(expireRing(ring), "BarterNotExpired");
+
+      await closeRing(ring);
+      expect(await provider.connection.getAccountInfo(ring)).to.equal(null);
+    });
+  });
+
+  describe("negotiation", () => {
+    const counterOffer = (offer: anchor.web3.PublicKey, need: anchor.web3.PublicKey, price: number, caller: anchor.web3.Keypair) =>
+      program.methods
+        .counterOffer(new anchor.BN(price))
+        .accounts({ global: globalPda, need, offer, caller: caller.publicKey })
+        .signers([caller])
+        .rpc();
+
+    it("settles a deal on the agreed counter price", async () => {
+      let global = await program.account.global.fetch(globalPda);
+      const needPda = findPda("need", global.needCounter);
+      await program.methods
+        .createNeed("Negotiated need", "desc", new anchor.BN(40_000_000), null, false)
+        .accounts({ global: globalPda, need: needPda, category: categoryPda, creator: creator.publicKey })
+        .signers([creator])
+        .rpc();
+      const need = await program.account.need.fetch(needPda);
+
+      global = await program.account.global.fetch(globalPda);
+      const offerPda = findPda("offer", global.offerCounter);
+      await program.methods
+        .createOffer(need.id, new anchor.BN(40_000_000), "Opening price", null, [20_000_000, 20_000_000].map((n) => new anchor.BN(n)))
+        .accounts({ global: globalPda, need: needPda, offer: offerPda, provider: providerAccount.publicKey })
+        .signers([providerAccount])
+        .rpc();
+
+      await expectError(counterOffer(offerPda, needPda, 35_000_000, providerAccount), "CannotCounterOwnProposal");
+      await counterOffer(offerPda, needPda, 25_000_000, creator);
+      await counterOffer(offerPda, needPda, 32_000_000, providerAccount);
+      await counterOffer(offerPda, needPda, 30_000_000, creator);
+
+      const dealPda = findPda("deal", global.dealCounter);
+      const accept = () =>
+        program.methods
+          .acceptOffer(new anchor.BN(30_000_000))
+          .accounts({
+            global: globalPda,
+            need: needPda,
+            offer: offerPda,
+            category: categoryPda,
+            deal: dealPda,
+            client: creator.publicKey,
+          })
+          .signers([creator])
+          .rpc();
+
+      // The creator's own counter isn't agreed until the provider accepts it
+      await expectError(accept(), "CounterPending");
+      await program.methods
+        .acceptCounterOffer()
+        .accounts({ global: globalPda, offer: offerPda, provider: providerAccount.publicKey })
+        .signers([providerAccount])
+        .rpc();
+      await accept();
+
+      const deal = await program.account.deal.fetch(dealPda);
+      expect(deal.amountLamports.toNumber()).to.equal(30_000_000);
+      expect(deal.milestones.map((amount) => amount.toNumber())).to.deep.equal([30_000_000]);
+      const offer = await program.account.offer.fetch(offerPda);
+      expect(offer.negotiationRound).to.equal(3);
+    });
+
+    it("bounds barter negotiation and accepts the agreed terms", async () => {
+      const global = await program.account.global.fetch(globalPda);
+      const barterPda = findPda("barter", global.barterCounter);
+      await program.methods
+        .createBarter("Logo", "Website", null, new anchor.BN(0), null, null)
+        .accounts({ global: globalPda, barter: barterPda, initiator: creator.publicKey })
+        .signers([creator])
+        .rpc();
+
+      const counter = (caller: anchor.web3.Keypair, want: string) =>
+        program.methods
+          .counterBarter("Logo", want)
+          .accounts({ global: globalPda, barter: barterPda, caller: caller.publicKey })
+          .signers([caller])
+          .rpc();
+
+      for (let round = 0; round < 6; round++) {
+        await counter(round % 2 === 0 ? providerAccount : creator, `Landing page v${round}`);
+      }
+      await expectError(counter(providerAccount, "One more round"), "TooManyRounds");
+
+      // The initiator made the last counter, so the counterpart can accept right away
+      await program.methods
+        .acceptBarter()
+        .accounts({ global: globalPda, barter: barterPda, caller: providerAccount.publicKey })
+        .signers([providerAccount])
+        .rpc();
+
+      const barter = await program.account.barter.fetch(barterPda);
+      expect(barter.status).to.deep.equal({ inProgress: {} });
+      expect(barter.counterpart.toString()).to.equal(providerAccount.publicKey.toString());
+      expect(barter.whatIWant).to.equal("Landing page v5");
+    });
+
+    it("doesn't let a counter claim an untargeted barter", async () => {
+      const latecomer = anchor.web3.Keypair.generate();
+      await provider.connection.confirmTransaction(
+        await provider.connection.requestAirdrop(latecomer.publicKey, anchor.web3.LAMPORTS_PER_SOL)
+      );
+
+      const global = await program.account.global.fetch(globalPda);
+      const barterPda = findPda("barter", global.barterCounter);
+      await program.methods
+        .createBarter("Copywriting", "Illustration", null, new anchor.BN(0), null, null)
+        .accounts({ global: globalPda, barter: barterPda, initiator: creator.publicKey })
+        .signers([creator])
+        .rpc();
+
+      const counter = (caller: anchor.web3.Keypair, want: string) =>
+        program.methods
+          .counterBarter("Copywriting", want)
+          .accounts({ global: globalPda, barter: barterPda, caller: caller.publicKey })
+          .signers([caller])
+          .rpc();
+      const accept = (caller: anchor.web3.Keypair) =>
+        program.methods
+          .acceptBarter()
+          .accounts({ global: globalPda, barter: barterPda, caller: caller.publicKey })
+          .signers([caller])
+          .rpc();
+
+      await counter(providerAccount, "Two illustrations");
+      expect((await program.account.barter.fetch(barterPda)).counterpart.toString()).to.equal(
+        anchor.web3.PublicKey.default.toString()
+      );
+      // Only the initiator can answer a pending counter
+      await expectError(counter(latecomer, "Three illustrations"), "CounterPending");
+      await expectError(accept(latecomer), "CounterPending");
+
+      await program.methods
+        .setPaused(false, true)
+        .accounts({ global: globalPda, authority: authority.publicKey })
+        .rpc();
+      try {
+        await expectError(counter(creator, "One illustration"), "Paused");
+      } finally {
+        await program.methods
+          .setPaused(false, false)
+          .accounts({ global: globalPda, authority: authority.publicKey })
+          .rpc();
+      }
+
+      await program.methods
+        .acceptCounterBarter()
+        .accounts({ global: globalPda, barter: barterPda, initiator: creator.publicKey })
+        .signers([creator])
+        .rpc();
+      // Whoever accepts the agreed terms first becomes the counterpart
+      await accept(latecomer);
+
+      const barter = await program.account.barter.fetch(barterPda);
+      expect(barter.status).to.deep.equal({ inProgress: {} });
+      expect(barter.counterpart.toString()).to.equal(latecomer.publicKey.toString());
+      expect(barter.whatIWant).to.equal("Two illustrations");
+    });
+
+    it("only negotiates offers on open needs while the marketplace runs", async () => {
+      const global = await program.account.global.fetch(globalPda);
+      const needPda = findPda("need", global.needCounter);
+      const offerPda = findPda("offer", global.offerCounter);
+      await program.methods
+        .createNeed("Withdrawn need", "desc", new anchor.BN(10_000_000), null, false)
+        .accounts({ global: globalPda, need: needPda, category: categoryPda, creator: creator.publicKey })
+        .signers([creator])
+        .rpc();
+      await program.methods
+        .createOffer(global.needCounter, new anchor.BN(10_000_000), "Opening price", null, [])
+        .accounts({ global: globalPda, need: needPda, offer: offerPda, provider: providerAccount.publicKey })
+        .signers([providerAccount])
+        .rpc();
+
+      await program.methods
+        .setPaused(true, false)
+        .accounts({ global: globalPda, authority: authority.publicKey })
+        .rpc();
+      try {
+        await expectError(counterOffer(offerPda, needPda, 8_000_000, creator), "Paused");
+      } finally {
+        await program.methods
+          .setPaused(false, false)
+          .accounts({ global: globalPda, authority: authority.publicKey })
+          .rpc();
+      }
+
+      await program.methods
+        .cancelNeed()
+        .accounts({ global: globalPda, need: needPda, creator: creator.publicKey })
+        .signers([creator])
+        .rpc();
+      await expectError(counterOffer(offerPda, needPda, 8_000_000, creator), "NeedNotOpen");
+    });
+  });
+
+  describe("dispute arbitration", () => {
+    const arbitrators = [anchor.web3.Keypair.generate(), anchor.web3.Keypair.generate()];
+
+    let poolPda: anchor.web3.PublicKey;
+    let dealPda: anchor.web3.PublicKey;
+    let needPda: anchor.web3.PublicKey;
+
+    const arbitratorPda = (wallet: anchor.web3.PublicKey) =>
+      anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("arbitrator"), globalPda.toBuffer(), wallet.toBuffer()],
+        program.programId
+      )[0];
+
+    // Each disputed milestone gets its own jury
+    const juryPda = (milestone: number) =>
+      anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("jury"), dealPda.toBuffer(), Buffer.from([milestone])],
+        program.programId
+      )[0];
+
+    const initPool = (jurorsPerDispute: number) =>
+      program.methods
+        .initArbitratorPool(new anchor.BN(100_000_000), jurorsPerDispute, 5_000, new anchor.BN(2), new anchor.BN(2))
+        .accounts({ global: globalPda, pool: poolPda, authority: authority.publicKey })
+        .rpc();
+
+    const join = (wallet: anchor.web3.Keypair, stake: number) =>
+      program.methods
+        .joinArbitratorPool(new anchor.BN(stake))
+        .accounts({ global: globalPda, pool: poolPda, arbitrator: arbitratorPda(wallet.publicKey), wallet: wallet.publicKey })
+        .signers([wallet])
+        .rpc();
+
+    const deliverAndDispute = async (reason: string) => {
+      await program.methods
+        .submitDelivery("QmArbitrated", "Disputed delivery")
+        .accounts({ global: globalPda, deal: dealPda, provider: providerAccount.publicKey })
+        .signers([providerAccount])
+        .rpc();
+      await program.methods
+        .raiseDispute(reason)
+        .accounts({ global: globalPda, deal: dealPda, caller: creator.publicKey })
+        .signers([creator])
+        .rpc();
+    };
+
+    const drawJury = (milestone: number) =>
+      program.methods
+        .drawJury()
+        .accounts({
+          global: globalPda,
+          pool: poolPda,
+          deal: dealPda,
+          jury: juryPda(milestone),
+          slotHashes: anchor.web3.SYSVAR_SLOT_HASHES_PUBKEY,
+          caller: creator.publicKey,
+        })
+        .signers([creator])
+        .rpc();
+
+    // Only the drawn jurors' accounts are passed, in jury order
+    const lockJury = async (milestone: number, jurors?: anchor.web3.PublicKey[]) => {
+      const jury = await program.account.jury.fetch(juryPda(milestone));
+      return program.methods
+        .lockJury()
+        .accounts({ global: globalPda, pool: poolPda, deal: dealPda, jury: juryPda(milestone), caller: creator.publicKey })
+        .remainingAccounts(
+          (jurors ?? jury.jurors.map((vote) => vote.wallet)).map((wallet) => ({
+            pubkey: arbitratorPda(wallet),
+            isSigner: false,
+            isWritable: true,
+          }))
+        )
+        .signers([creator])
+        .rpc();
+    };
+
+    const commitVote = (juror: anchor.web3.Keypair, milestone: number, commitment: Buffer) =>
+      program.methods
+        .commitVote(Array.from(commitment))
+        .accounts({
+          global: globalPda,
+          deal: dealPda,
+          jury: juryPda(milestone),
+          juror: juror.publicKey,
+        })
+        .signers([juror])
+        .rpc();
+
+    const resolve = (resolution: object, resolver: anchor.web3.Keypair, jury: anchor.web3.PublicKey | null) =>
+      program.methods
+        .resolveDispute(resolution as any)
+        .accounts({
+          global: globalPda,
+          deal: dealPda,
+          need: needPda,
+          resolver: resolver.publicKey,
+          jury,
+          client: creator.publicKey,
+          provider: providerAccount.publicKey,
+          treasury: authority.publicKey,
+        })
+        .signers([resolver])
+        .rpc();
+
+    before(async () => {
+      for (const arbitrator of arbitrators) {
+        const sig = await provider.connection.requestAirdrop(arbitrator.publicKey, anchor.web3.LAMPORTS_PER_SOL);
+        await provider.connection.confirmTransaction(sig);
+      }
+      poolPda = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("arbitrator_pool"), globalPda.toBuffer()],
+        program.programId
+      )[0];
+
+      ({ need: needPda, deal: dealPda } = await openDeal("Arbitrated need", 30_000_000, [10_000_000, 20_000_000]));
+      await deliverAndDispute("Not what was asked for");
+    });
+
+    it("validates the pool config and minimum stake", async () => {
+      await expectError(initPool(2), "InvalidPoolConfig");
+      await initPool(1);
+
+      await expectError(join(arbitrators[0], 50_000_000), "StakeTooLow");
+      for (const arbitrator of arbitrators) {
+        await join(arbitrator, 100_000_000);
+      }
+
+      const pool = await program.account.arbitratorPool.fetch(poolPda);
+      expect(pool.members.length).to.equal(2);
+    });
+
+    it("binds the dispute to the jury's revealed verdict", async () => {
+      await drawJury(0);
+
+      const jury = await program.account.jury.fetch(juryPda(0));
+      expect(jury.jurors.length).to.equal(1);
+      expect(jury.milestone).to.equal(0);
+      expect(jury.locked).to.equal(false);
+      const juror = arbitrators.find((arbitrator) => arbitrator.publicKey.equals(jury.jurors[0].wallet))!;
+      const outsider = arbitrators.find((arbitrator) => arbitrator !== juror)!;
+
+      // Commitment is sha256(borsh(resolution) || salt || juror); PayProvider is variant 1
+      const salt = Buffer.alloc(32, 7);
+      const commitment = createHash("sha256")
+        .update(Buffer.concat([Buffer.from([1]), salt, juror.publicKey.toBuffer()]))
+        .digest();
+      // Voting opens once the drawn jurors' stakes are locked
+      await expectError(commitVote(juror, 0, commitment), "JuryNotLocked");
+      await expectError(lockJury(0, [outsider.publicKey]), "InvalidArbitratorAccount");
+      await lockJury(0);
+      await expectError(lockJury(0), "JuryAlreadyLocked");
+      expect((await program.account.arbitrator.fetch(arbitratorPda(juror.publicKey))).activeCases).to.equal(1);
+      expect((await program.account.arbitrator.fetch(arbitratorPda(outsider.publicKey))).activeCases).to.equal(0);
+
+      await expectError(commitVote(outsider, 0, commitment), "NotJuror");
+      await commitVote(juror, 0, commitment);
+
+      await sleep(3_000);
+      await program.methods
+        .revealVote({ payProvider: {} } as any, Array.from(salt))
+        .accounts({ global: globalPda, deal: dealPda, jury: juryPda(0), juror: juror.publicKey })
+        .signers([juror])
+        .rpc();
+      await program.methods
+        .finalizeJury()
+        .accounts({ global: globalPda, pool: poolPda, deal: dealPda, jury: juryPda(0), treasury: authority.publicKey, caller: creator.publicKey })
+        .remainingAccounts([{ pubkey: arbitratorPda(juror.publicKey), isSigner: false, isWritable: true }])
+        .signers([creator])
+        .rpc();
+
+      await expectError(resolve({ payProvider: {} }, creator, null), "JuryRequired");
+      await expectError(resolve({ refundClient: {} }, creator, juryPda(0)), "VerdictMismatch");
+      await resolve({ payProvider: {} }, creator, juryPda(0));
+
+      // The verdict covered the first milestone only; the deal moves on to the second
+      const deal = await program.account.deal.fetch(dealPda);
+      expect(deal.status).to.deep.equal({ inProgress: {} });
+      expect(deal.currentMilestone).to.equal(1);
+      expect(deal.arbitrated).to.equal(false);
+      const arbitrator = await program.account.arbitrator.fetch(arbitratorPda(juror.publicKey));
+      expect(arbitrator.activeCases).to.equal(0);
+      expect(arbitrator.slashedLamports.toNumber()).to.equal(0);
+    });
+
+    it("gives the next milestone's dispute a fresh trial", async () => {
+      await deliverAndDispute("Second milestone is broken too");
+
+      // The first jury's verdict can't be replayed against the new dispute
+      const stranger = anchor.web3.Keypair.generate();
+      const sig = await provider.connection.requestAirdrop(stranger.publicKey, anchor.web3.LAMPORTS_PER_SOL);
+      await provider.connection.confirmTransaction(sig);
+      await expectError(resolve({ payProvider: {} }, stranger, juryPda(0)), "ConstraintSeeds");
+      await expectError(resolve({ payProvider: {} }, stranger, null), "NotAuthority");
+
+      await drawJury(1);
+      await lockJury(1);
+      const jury = await program.account.jury.fetch(juryPda(1));
+      expect(jury.milestone).to.equal(1);
+      expect(jury.finalized).to.equal(false);
+      await expectError(resolve({ refundClient: {} }, stranger, juryPda(1)), "VerdictMismatch");
+    });
+
+    it("refuses to reveal a split over 100%", async () => {
+      const jury = await program.account.jury.fetch(juryPda(1));
+      const juror = arbitrators.find((arbitrator) => arbitrator.publicKey.equals(jury.jurors[0].wallet))!;
+
+      // Split is variant 2, followed by provider_bps as a little-endian u16
+      const salt = Buffer.alloc(32, 9);
+      const vote = Buffer.alloc(3);
+      vote.writeUInt8(2, 0);
+      vote.writeUInt16LE(10_001, 1);
+      const commitment = createHash("sha256")
+        .update(Buffer.concat([vote, salt, juror.publicKey.toBuffer()]))
+        .digest();
+      await commitVote(juror, 1, commitment);
+
+      await sleep(3_000);
+      await expectError(
+        program.methods
+          .revealVote({ split: { providerBps: 10_001 } } as any, Array.from(salt))
+          .accounts({ global: globalPda, deal: dealPda, jury: juryPda(1), juror: juror.publicKey })
+          .signers([juror])
+          .rpc(),
+        "InvalidSplit"
+      );
+    });
+
+    it("keeps a drawn juror's stake locked until the jury is finalized", async () => {
+      const jury = await program.account.jury.fetch(juryPda(1));
+      const juror = arbitrators.find((arbitrator) => arbitrator.publicKey.equals(jury.jurors[0].wallet))!;
+      const withdraw = () =>
+        program.methods
+          .withdrawArbitratorStake()
+          .accounts({ global: globalPda, arbitrator: arbitratorPda(juror.publicKey), wallet: juror.publicKey })
+          .signers([juror])
+          .rpc();
+
+      await program.methods
+        .leaveArbitratorPool()
+        .accounts({ global: globalPda, pool: poolPda, arbitrator: arbitratorPda(juror.publicKey), wallet: juror.publicKey })
+        .signers([juror])
+        .rpc();
+      // Unbonding is 2 * (commit + reveal) = 8 seconds; the open jury still holds the stake
+      await sleep(9_000);
+      await expectError(withdraw(), "StakeLocked");
+
+      // The juror never revealed a valid vote, so finalizing slashes half the stake
+      await program.methods
+        .finalizeJury()
+        .accounts({ global: globalPda, pool: poolPda, deal: dealPda, jury: juryPda(1), treasury: authority.publicKey, caller: creator.publicKey })
+        .remainingAccounts([{ pubkey: arbitratorPda(juror.publicKey), isSigner: false, isWritable: true }])
+        .signers([creator])
+        .rpc();
+      const arbitrator = await program.account.arbitrator.fetch(arbitratorPda(juror.publicKey));
+      expect(arbitrator.activeCases).to.equal(0);
+      expect(arbitrator.slashedLamports.toNumber()).to.equal(50_000_000);
+      // Without a verdict the dispute falls back to the authority
+      expect((await program.account.jury.fetch(juryPda(1))).verdict).to.equal(null);
+      expect((await program.account.deal.fetch(dealPda)).arbitrated).to.equal(false);
+
+      await withdraw();
+      expect(await provider.connection.getAccountInfo(arbitratorPda(juror.publicKey))).to.equal(null);
+    });
+  });
+
+  describe("jury quorum", () => {
+    // A separate marketplace, so its pool can seat three jurors with one to spare
+    const quorumId = new anchor.BN(4);
+    const arbitrators = [0, 1, 2, 3].map(() => anchor.web3.Keypair.generate());
+
+    let quorumGlobal: anchor.web3.PublicKey;
+    let poolPda: anchor.web3.PublicKey;
+    let category: anchor.web3.PublicKey;
+    let needPda: anchor.web3.PublicKey;
+    let dealPda: anchor.web3.PublicKey;
+    let juryPda: anchor.web3.PublicKey;
+
+    const pdaUnder = (seed: string, id: anchor.BN) =>
+      anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from(seed), quorumGlobal.toBuffer(), id.toArrayLike(Buffer, "le", 8)],
+        program.programId
+      )[0];
+
+    const arbitratorPda = (wallet: anchor.web3.PublicKey) =>
+      anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("arbitrator"), quorumGlobal.toBuffer(), wallet.toBuffer()],
+        program.programId
+      )[0];
+
+    const jurorAccounts = async () =>
+      (await program.account.jury.fetch(juryPda)).jurors.map((vote) => ({
+        pubkey: arbitratorPda(vote.wallet),
+        isSigner: false,
+        isWritable: true,
+      }));
+
+    // Opens and disputes deal `id`, pointing the describe's PDAs at it
+    const openDispute = async (id: number) => {
+      needPda = pdaUnder("need", new anchor.BN(id));
+      const offerPda = pdaUnder("offer", new anchor.BN(id));
+      dealPda = pdaUnder("deal", new anchor.BN(id));
+      juryPda = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("jury"), dealPda.toBuffer(), Buffer.from([0])],
+        program.programId
+      )[0];
+
+      await program.methods
+        .createNeed("Quorum need", "desc", new anchor.BN(10_000_000), null, false)
+        .accounts({ global: quorumGlobal, need: needPda, category, creator: creator.publicKey })
+        .signers([creator])
+        .rpc();
+      await program.methods
+        .createOffer(new anchor.BN(id), new anchor.BN(10_000_000), "Quorum offer", null, [])
+        .accounts({ global: quorumGlobal, need: needPda, offer: offerPda, provider: providerAccount.publicKey })
+        .signers([providerAccount])
+        .rpc();
+      await program.methods
+        .acceptOffer(new anchor.BN(10_000_000))
+        .accounts({ global: quorumGlobal, need: needPda, offer: offerPda, category, deal: dealPda, client: creator.publicKey })
+        .signers([creator])
+        .rpc();
+      await program.methods
+        .raiseDispute("Nothing arrived")
+        .accounts({ global: quorumGlobal, deal: dealPda, caller: creator.publicKey })
+        .signers([creator])
+        .rpc();
+    };
+
+    const drawJury = () =>
+      program.methods
+        .drawJury()
+        .accounts({
+          global: quorumGlobal,
+          pool: poolPda,
+          deal: dealPda,
+          jury: juryPda,
+          slotHashes: anchor.web3.SYSVAR_SLOT_HASHES_PUBKEY,
+          caller: creator.publicKey,
+        })
+        .signers([creator])
+        .rpc();
+
+    const lockJury = async () =>
+      program.methods
+        .lockJury()
+        .accounts({ global: quorumGlobal, pool: poolPda, deal: dealPda, jury: juryPda, caller: creator.publicKey })
+        .remainingAccounts(await jurorAccounts())
+        .signers([creator])
+        .rpc();
+
+    // Resolves as the authority unless another signer is given
+    const resolve = (signer?: anchor.web3.Keypair) => {
+      const builder = program.methods.resolveDispute({ refundClient: {} } as any).accounts({
+        global: quorumGlobal,
+        deal: dealPda,
+        need: needPda,
+        resolver: (signer ?? authority).publicKey,
+        jury: juryPda,
+        client: creator.publicKey,
+        provider: providerAccount.publicKey,
+        treasury: authority.publicKey,
+      });
+      return (signer ? builder.signers([signer]) : builder).rpc();
+    };
+
+    before(async () => {
+      for (const arbitrator of arbitrators) {
+        const sig = await provider.connection.requestAirdrop(arbitrator.publicKey, anchor.web3.LAMPORTS_PER_SOL);
+        await provider.connection.confirmTransaction(sig);
+      }
+
+      [quorumGlobal] = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("global"), quorumId.toArrayLike(Buffer, "le", 8)],
+        program.programId
+      );
+      poolPda = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("arbitrator_pool"), quorumGlobal.toBuffer()],
+        program.programId
+      )[0];
+      category = pdaUnder("category", new anchor.BN(0));
+
+      await program.methods
+        .initialize(quorumId)
+        .accounts({ global: quorumGlobal, authority: authority.publicKey })
+        .rpc();
+      await program.methods
+        .setEvidenceWindow(new anchor.BN(0))
+        .accounts({ global: quorumGlobal, authority: authority.publicKey })
+        .rpc();
+      await program.methods
+        .createCategory("development", null, new anchor.BN(0))
+        .accounts({
+          global: quorumGlobal,
+          category,
+          categoryName: categoryNamePda("development", quorumGlobal),
+          authority: authority.publicKey,
+        })
+        .rpc();
+      await program.methods
+        .initArbitratorPool(new anchor.BN(100_000_000), 3, 5_000, new anchor.BN(2), new anchor.BN(2))
+        .accounts({ global: quorumGlobal, pool: poolPda, authority: authority.publicKey })
+        .rpc();
+      for (const arbitrator of arbitrators) {
+        await program.methods
+          .joinArbitratorPool(new anchor.BN(100_000_000))
+          .accounts({
+            global: quorumGlobal,
+            pool: poolPda,
+            arbitrator: arbitratorPda(arbitrator.publicKey),
+            wallet: arbitrator.publicKey,
+          })
+          .signers([arbitrator])
+          .rpc();
+      }
+    });
+
+    it("hands the dispute back to the authority when the jury is never locked", async () => {
+      await openDispute(0);
+      await drawJury();
+
+      // A drawn juror walks away before anyone locks the jury
+      const jury = await program.account.jury.fetch(juryPda);
+      const [deserter, stayer] = jury.jurors.map(
+        (vote) => arbitrators.find((arbitrator) => arbitrator.publicKey.equals(vote.wallet))!
+      );
+      await program.methods
+        .leaveArbitratorPool()
+        .accounts({ global: quorumGlobal, pool: poolPda, arbitrator: arbitratorPda(deserter.publicKey), wallet: deserter.publicKey })
+        .signers([deserter])
+        .rpc();
+      // Unbonding is 2 * (commit + reveal) = 8 seconds, well past the 4 second lock window
+      await sleep(9_000);
+      await program.methods
+        .withdrawArbitratorStake()
+        .accounts({ global: quorumGlobal, arbitrator: arbitratorPda(deserter.publicKey), wallet: deserter.publicKey })
+        .signers([deserter])
+        .rpc();
+      await expectError(lockJury(), "JuryLockExpired");
+
+      // The lapsed jury doesn't bind, so only the authority can settle the dispute
+      await expectError(resolve(creator), "NotAuthority");
+      await resolve();
+      expect((await program.account.deal.fetch(dealPda)).status).to.deep.equal({ cancelled: {} });
+
+      // Votes are no longer taken once the dispute is settled
+      await expectError(
+        program.methods
+          .commitVote(Array.from(Buffer.alloc(32)))
+          .accounts({ global: quorumGlobal, deal: dealPda, jury: juryPda, juror: stayer.publicKey })
+          .signers([stayer])
+          .rpc(),
+        "DealNotDisputed"
+      );
+    });
+
+    it("needs a majority of the whole jury, not just of those who revealed", async () => {
+      await openDispute(1);
+      await drawJury();
+      await lockJury();
+
+      // One juror votes; the other two stay silent
+      const jury = await program.account.jury.fetch(juryPda);
+      expect(jury.jurors.length).to.equal(3);
+      const voter = arbitrators.find((arbitrator) => arbitrator.publicKey.equals(jury.jurors[0].wallet))!;
+      const salt = Buffer.alloc(32, 3);
+      const commitment = createHash("sha256")
+        .update(Buffer.concat([Buffer.from([0]), salt, voter.publicKey.toBuffer()]))
+        .digest();
+      await program.methods
+        .commitVote(Array.from(commitment))
+        .accounts({ global: quorumGlobal, deal: dealPda, jury: juryPda, juror: voter.publicKey })
+        .signers([voter])
+        .rpc();
+      await sleep(3_000);
+      await program.methods
+        .revealVote({ refundClient: {} } as any, Array.from(salt))
+        .accounts({ global: quorumGlobal, deal: dealPda, jury: juryPda, juror: voter.publicKey })
+        .signers([voter])
+        .rpc();
+
+      await sleep(3_000);
+      await program.methods
+        .finalizeJury()
+        .accounts({ global: quorumGlobal, pool: poolPda, deal: dealPda, jury: juryPda, treasury: authority.publicKey, caller: creator.publicKey })
+        .remainingAccounts(await jurorAccounts())
+        .signers([creator])
+        .rpc();
+
+      // A lone revealer is no quorum, so the authority decides
+      expect((await program.account.jury.fetch(juryPda)).verdict).to.equal(null);
+      expect((await program.account.deal.fetch(dealPda)).arbitrated).to.equal(false);
+      expect((await program.account.arbitrator.fetch(arbitratorPda(voter.publicKey))).slashedLamports.toNumber()).to.equal(0);
+      for (const vote of jury.jurors.slice(1)) {
+        const silent = await program.account.arbitrator.fetch(arbitratorPda(vote.wallet));
+        expect(silent.slashedLamports.toNumber()).to.equal(50_000_000);
+      }
+
+      await resolve();
+      expect((await program.account.deal.fetch(dealPda)).status).to.deep.equal({ cancelled: {} });
+    });
+  });
+
+  describe("split dispute resolution", () => {
+    it("divides the escrow and marks the deal partially completed", async () => {
+      const { need: needPda, deal: dealPda } = await openDeal("Half delivered", 40_000_000);
+      await program.methods
+        .raiseDispute("Only half of the work arrived")
+        .accounts({ global: globalPda, deal: dealPda, caller: creator.publicKey })
+        .signers([creator])
+        .rpc();
+
+      const split = (providerBps: number) =>
+        program.methods
+          .resolveDispute({ split: { providerBps } } as any)
+          .accounts({
+            global: globalPda,
+            deal: dealPda,
+            need: needPda,
+            resolver: authority.publicKey,
+            client: creator.publicKey,
+            provider: providerAccount.publicKey,
+            treasury: authority.publicKey,
+          })
+          .rpc();
+
+      await expectError(split(10_001), "InvalidSplit");
+
+      const clientBalanceBefore = await provider.connection.getBalance(creator.publicKey);
+      await split(5_000);
+
+      const deal = await program.account.deal.fetch(dealPda);
+      expect(deal.status).to.deep.equal({ partiallyCompleted: {} });
+      expect(deal.releasedLamports.toNumber()).to.equal(40_000_000);
+      expect((await program.account.need.fetch(needPda)).status).to.deep.equal({ partiallyCompleted: {} });
+      expect(await provider.connection.getBalance(creator.publicKey)).to.equal(clientBalanceBefore + 20_000_000);
+    });
+  });
+
+  describe("dispute evidence", () => {
+    let needPda: anchor.web3.PublicKey;
+    let dealPda: anchor.web3.PublicKey;
+    let evidencePda: anchor.web3.PublicKey;
+
+    const setEvidenceWindow = (secs: number) =>
+      program.methods
+        .setEvidenceWindow(new anchor.BN(secs))
+        .accounts({ global: globalPda, authority: authority.publicKey })
+        .rpc();
+
+    const submitEvidence = (caller: anchor.web3.Keypair, contentHash: string, uri: string) =>
+      program.methods
+        .submitEvidence(contentHash, uri)
+        .accounts({ global: globalPda, deal: dealPda, evidence: evidencePda, caller: caller.publicKey })
+        .signers([caller])
+        .rpc();
+
+    const resolve = () =>
+      program.methods
+        .resolveDispute({ refundClient: {} } as any)
+        .accounts({
+          global: globalPda,
+          deal: dealPda,
+          need: needPda,
+          resolver: authority.publicKey,
+          client: creator.publicKey,
+          provider: providerAccount.publicKey,
+          treasury: authority.publicKey,
+        })
+        .rpc();
+
+    before(async () => {
+      await setEvidenceWindow(3);
+
+      ({ need: needPda, deal: dealPda } = await openDeal("Contested need", 10_000_000));
+      await program.methods
+        .raiseDispute("Provider went silent")
+        .accounts({ global: globalPda, deal: dealPda, caller: creator.publicKey })
+        .signers([creator])
+        .rpc();
+
+      evidencePda = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("evidence"), dealPda.toBuffer(), Buffer.from([0])],
+        program.programId
+      )[0];
+    });
+
+    after(async () => {
+      await setEvidenceWindow(0);
+    });
+
+    it("collects evidence from both parties and holds resolution until the window closes", async () => {
+      await submitEvidence(creator, "QmClientChat", "ipfs://QmClientChat");
+      await submitEvidence(providerAccount, "QmProviderDraft", "ipfs://QmProviderDraft");
+
+      const evidence = await program.account.disputeEvidence.fetch(evidencePda);
+      expect(evidence.entries.map((entry) => entry.uri)).to.deep.equal([
+        "ipfs://QmClientChat",
+        "ipfs://QmProviderDraft",
+      ]);
+      expect(evidence.openedBy.toString()).to.equal(creator.publicKey.toString());
+
+      await expectError(resolve(), "EvidenceWindowOpen");
+      // Jurors are only drawn once the evidence they'll weigh is in
+      await expectError(
+        program.methods
+          .drawJury()
+          .accounts({
+            global: globalPda,
+            pool: anchor.web3.PublicKey.findProgramAddressSync(
+              [Buffer.from("arbitrator_pool"), globalPda.toBuffer()],
+              program.programId
+            )[0],
+            deal: dealPda,
+            jury: anchor.web3.PublicKey.findProgramAddressSync(
+              [Buffer.from("jury"), dealPda.toBuffer(), Buffer.from([0])],
+              program.programId
+            )[0],
+            slotHashes: anchor.web3.SYSVAR_SLOT_HASHES_PUBKEY,
+            caller: creator.publicKey,
+          })
+          .signers([creator])
+          .rpc(),
+        "EvidenceWindowOpen"
+      );
+
+      await sleep(4_000);
+      await expectError(submitEvidence(providerAccount, "QmLate", "ipfs://QmLate"), "EvidenceWindowClosed");
+      await resolve();
+
+      await program.methods
+        .closeDisputeEvidence()
+        .accounts({ evidence: evidencePda, deal: dealPda, openedBy: creator.publicKey })
+        .signers([creator])
+        .rpc();
+      expect(await provider.connection.getAccountInfo(evidencePda)).to.equal(null);
+    });
+  });
+
+  describe("mutual cancellation", () => {
+    const setFeeBps = (feeBps: number) =>
+      program.methods
+        .setFeeConfig(feeBps, authority.publicKey)
+        .accounts({ global: globalPda, treasury: authority.publicKey, authority: authority.publicKey })
+        .rpc();
+
+    it("refunds the client less the agreed kill fee and reopens the need", async () => {
+      // The deal snapshots a protocol fee, which the kill fee must not be charged
+      await setFeeBps(500);
+      let needPda: anchor.web3.PublicKey;
+      let dealPda: anchor.web3.PublicKey;
+      try {
+        ({ need: needPda, deal: dealPda } = await openDeal("Change of plans", 30_000_000));
+      } finally {
+        await setFeeBps(0);
+      }
+
+      const mutualCancel = (caller: anchor.web3.Keypair, killFee: number, reopenNeed: boolean) =>
+        program.methods
+          .mutualCancel(new anchor.BN(killFee), reopenNeed)
+          .accounts({
+            global: globalPda,
+            deal: dealPda,
+            need: needPda,
+            client: creator.publicKey,
+            provider: providerAccount.publicKey,
+            caller: caller.publicKey,
+          })
+          .signers([caller])
+          .rpc();
+
+      await expectError(mutualCancel(creator, 30_000_001, true), "InvalidKillFee");
+
+      // A different fee from the provider is a new proposal, not an agreement
+      await mutualCancel(creator, 2_000_000, true);
+      await mutualCancel(providerAccount, 5_000_000, false);
+      let deal = await program.account.deal.fetch(dealPda);
+      expect(deal.status).to.deep.equal({ inProgress: {} });
+      expect(deal.cancelProposal.proposedBy.toString()).to.equal(providerAccount.publicKey.toString());
+
+      const clientBalanceBefore = await provider.connection.getBalance(creator.publicKey);
+      const providerBalanceBefore = await provider.connection.getBalance(providerAccount.publicKey);
+      await mutualCancel(creator, 5_000_000, true);
+
+      deal = await program.account.deal.fetch(dealPda);
+      expect(deal.status).to.deep.equal({ cancelled: {} });
+      expect(deal.feeBps).to.equal(500);
+      expect(deal.cancelProposal).to.equal(null);
+      expect((await program.account.need.fetch(needPda)).status).to.deep.equal({ open: {} });
+      expect(await provider.connection.getBalance(creator.publicKey)).to.equal(clientBalanceBefore + 25_000_000);
+      expect(await provider.connection.getBalance(providerAccount.publicKey)).to.equal(
+        providerBalanceBefore + 5_000_000
+      );
+    });
+
+    it("takes paid milestones off the reopened need's budget", async () => {
+      const { need: needPda, deal: dealPda } = await openDeal("Rescoped job", 10_000_000, [4_000_000, 6_000_000]);
+      await program.methods
+        .submitDelivery("QmFirstHalf", "First milestone")
+        .accounts({ global: globalPda, deal: dealPda, provider: providerAccount.publicKey })
+        .signers([providerAccount])
+        .rpc();
+      await program.methods
+        .confirmDelivery()
+        .accounts({
+          global: globalPda,
+          deal: dealPda,
+          need: needPda,
+          client: creator.publicKey,
+          provider: providerAccount.publicKey,
+          treasury: authority.publicKey,
+        })
+        .signers([creator])
+        .rpc();
+
+      for (const caller of [creator, providerAccount]) {
+        await program.methods
+          .mutualCancel(new anchor.BN(1_000_000), true)
+          .accounts({
+            global: globalPda,
+            deal: dealPda,
+            need: needPda,
+            client: creator.publicKey,
+            provider: providerAccount.publicKey,
+            caller: caller.publicKey,
+          })
+          .signers([caller])
+          .rpc();
+      }
+
+      // Only the paid milestone comes off; the kill fee doesn't buy any of the work
+      const need = await program.account.need.fetch(needPda);
+      expect(need.status).to.deep.equal({ open: {} });
+      expect(need.budgetLamports.toNumber()).to.equal(6_000_000);
+    });
+  });
+
+  describe("provider withdrawal", () => {
+    it("refunds the client, reopens the need and records the withdrawal", async () => {
+      const { need: needPda, deal: dealPda } = await openDeal("Abandoned job", 15_000_000);
+
+      const reputationPda = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("reputation"), globalPda.toBuffer(), providerAccount.publicKey.toBuffer()],
+        program.programId
+      )[0];
+      const withdrawalsBefore = (await program.account.reputation.fetchNullable(reputationPda))?.withdrawals.toNumber() ?? 0;
+
+      const withdraw = (caller: anchor.web3.Keypair) =>
+        program.methods
+          .withdrawFromDeal()
+          .accounts({
+            global: globalPda,
+            deal: dealPda,
+            need: needPda,
+            client: creator.publicKey,
+            provider: caller.publicKey,
+          })
+          .signers([caller])
+          .rpc();
+
+      await expectError(withdraw(creator), "NotProvider");
+
+      const clientBalanceBefore = await provider.connection.getBalance(creator.publicKey);
+      await withdraw(providerAccount);
+
+      const deal = await program.account.deal.fetch(dealPda);
+      expect(deal.status).to.deep.equal({ cancelled: {} });
+      expect((await program.account.need.fetch(needPda)).status).to.deep.equal({ open: {} });
+      expect(await provider.connection.getBalance(creator.publicKey)).to.equal(clientBalanceBefore + 15_000_000);
+      const reputation = await program.account.reputation.fetch(reputationPda);
+      expect(reputation.withdrawals.toNumber()).to.equal(withdrawalsBefore + 1);
+    });
+
+    it("takes paid milestones off the reopened need's budget", async () => {
+      const { need: needPda, deal: dealPda } = await openDeal("Half-built job", 10_000_000, [4_000_000, 6_000_000]);
+      await program.methods
+        .submitDelivery("QmFirstHalf", "First milestone")
+        .accounts({ global: globalPda, deal: dealPda, provider: providerAccount.publicKey })
+        .signers([providerAccount])
+        .rpc();
+      await program.methods
+        .confirmDelivery()
+        .accounts({
+          global: globalPda,
+          deal: dealPda,
+          need: needPda,
+          client: creator.publicKey,
+          provider: providerAccount.publicKey,
+          treasury: authority.publicKey,
+        })
+        .signers([creator])
+        .rpc();
+
+      await program.methods
+        .withdrawFromDeal()
+        .accounts({
+          global: globalPda,
+          deal: dealPda,
+          need: needPda,
+          client: creator.publicKey,
+          provider: providerAccount.publicKey,
+        })
+        .signers([providerAccount])
+        .rpc();
+
+      const need = await program.account.need.fetch(needPda);
+      expect(need.status).to.deep.equal({ open: {} });
+      expect(need.budgetLamports.toNumber()).to.equal(6_000_000);
+    });
+  });
+});